@@ -1,68 +1,223 @@
-use crate::helpers::escape;
+use crate::{filters::*, helpers::edit_until_valid};
+use std::io::Read;
 
 use super::*;
 
-pub fn create_template(
-    name: String,
-    default_path: Option<PathBuf>,
-    source: Option<PathBuf>,
-) -> Result<()> {
-    let source = source
-        .map(std::fs::read_to_string)
-        .transpose()?
-        .unwrap_or_default();
+pub fn create_template(args: CreateArgs) -> Result<()> {
+    let CreateArgs {
+        name,
+        default_path,
+        source,
+        delimiters,
+        template_file,
+        from_stdin,
+        defaults,
+        descriptions,
+        vars_file,
+        no_prompt,
+    } = args;
+    // prompts would read from the template
+    let no_prompt = no_prompt || from_stdin;
+
+    let vars = match vars_file {
+        Some(path) => toml::from_str(&std::fs::read_to_string(&path)?)
+            .map_err(|e| eyre!("couldn't read `{}`: {e}", path.display()))?,
+        None => Vars::default(),
+    };
+    let delimiters = match (delimiters, &vars.delimiters) {
+        (Some(d), _) => d,
+        (None, Some((start, end))) => Delimiters::new(start, end)?,
+        (None, None) => Delimiters::default(),
+    };
+
+    let custom_filters = read_custom_filters()?;
+    let custom_filters = parse_custom_filters(&custom_filters);
+    let validate = |t: &str| {
+        let partials = Partials::load(t, &delimiters, read_partial)?;
+        let parsed = parse_template(t, &delimiters, &partials)?;
+        validate_filters(&parsed, &custom_filters)
+    };
+
+    let template = if let Some(path) = template_file {
+        std::fs::read_to_string(path)?
+    } else if from_stdin {
+        let mut template = String::new();
+        io::stdin().read_to_string(&mut template)?;
+        template
+    } else if no_prompt {
+        return Err(eyre!(
+            "`--no-prompt` needs the template from `--template-file` or `--from-stdin`"
+        ));
+    } else {
+        let source = source
+            .map(std::fs::read_to_string)
+            .transpose()?
+            .unwrap_or_default();
+        edit_until_valid(&source, validate)?
+    };
+    validate(&template)?;
 
-    let template = edit::edit(source)?;
+    let partials = Partials::load(&template, &delimiters, read_partial)?;
+    let mut parsed = parse_template(&template, &delimiters, &partials)?;
 
-    let mut parsed = parse_template(&template)?;
+    let default_path = default_path
+        .or(vars.default_path.clone())
+        .or_else(|| parsed.front_matter.as_ref().and_then(|f| f.path.clone()));
+    if parsed.is_generator() && default_path.is_some() {
+        return Err(eyre!(
+            "templates with file blocks can't have a default path, since each file has its own"
+        ));
+    }
+    let default_path = default_path.as_ref().map(|p| p.to_string_lossy());
+    parse_path(&mut parsed, default_path.as_deref())?;
+    validate_filters(&parsed, &custom_filters)?;
+    apply_vars(&mut parsed, &vars, &defaults, &descriptions)?;
+
+    // templates with front matter already have everything they need
+    if parsed.front_matter.is_none() && !no_prompt {
+        ask_defaults_and_descriptions(&mut parsed, &vars)?;
+    }
+
+    let out = generate_bash_script(&name, parsed, &custom_filters);
+
+    crate::helpers::write_template(&name, &out)
+}
+
+/// applies the types, defaults and descriptions given in the command line, or in a vars file
+///
+/// the ones in the command line win over the vars file, and both win over the template
+pub fn apply_vars(
+    t: &mut Template,
+    vars: &Vars,
+    defaults: &[(String, String)],
+    descriptions: &[(String, String)],
+) -> Result<()> {
+    fn find<'v, 'a>(variables: &'v mut [Variable<'a>], name: &str) -> Result<&'v mut Variable<'a>> {
+        variables
+            .iter_mut()
+            .find(|v| v.variable == name)
+            .ok_or_else(|| eyre!("variable {name} isn't used in the template"))
+    }
 
-    ask_defaults_and_descriptions(&mut parsed)?;
+    for (name, kind) in &vars.types {
+        find(&mut t.variables, name)?.kind =
+            kind.parse().map_err(|e| eyre!("type of {name}: {e}"))?;
+    }
 
-    let out = generate_bash_script(&name, parsed, default_path);
+    let defaults = vars
+        .defaults
+        .iter()
+        .chain(defaults.iter().map(|(n, d)| (n, d)));
+    for (name, default) in defaults {
+        let v = find(&mut t.variables, name)?;
+        v.default = check_default(&v.kind, default)
+            .map_err(|e| e.wrap_err(format!("default for {name} doesn't fit its type")))?;
+    }
 
-    // save to file
-    let out_path = format!(".moho/{name}.mh");
-    std::fs::create_dir_all(".moho")?;
-    let mut file = File::create(&out_path)?;
-    file.write_all(out.as_bytes())?;
-    crate::helpers::make_executable(&out_path)?;
+    let descriptions = vars
+        .descriptions
+        .iter()
+        .chain(descriptions.iter().map(|(n, d)| (n, d)));
+    for (name, description) in descriptions {
+        find(&mut t.variables, name)?.description =
+            Some(description.clone()).filter(|d| !d.is_empty());
+    }
 
     Ok(())
 }
 
-fn ask_defaults_and_descriptions(t: &mut Template) -> Result<()> {
+/// asks for the types that weren't given in a vars file, and for the defaults and descriptions
+/// that weren't written in the template or given in the command line
+fn ask_defaults_and_descriptions(t: &mut Template, vars: &Vars) -> Result<()> {
     let mut s = String::new();
 
     for v in &mut t.variables {
-        if v.variable == "name" {
+        // variables with everything written in the template don't need any questions
+        if v.variable == "name" || (v.default.is_some() && v.description.is_some()) {
             continue;
         }
 
-        print!(
-            "default value for {} (leave empty for no default): ",
-            v.variable
-        );
-        io::stdout().flush()?;
-        io::stdin().read_line(&mut s)?;
-        let default = escape(s.trim());
-        // escape
-        if !default.is_empty() {
-            v.default = Some(default);
+        // types from the vars file were already checked against the default
+        let inline_default = v.default.clone();
+        if !vars.types.contains_key(v.variable) {
+            v.default = None;
+            v.kind = loop {
+                print!(
+                    "type of {} (string, bool, integer, or choices like `a|b`, \
+                     leave empty for string): ",
+                    v.variable
+                );
+                io::stdout().flush()?;
+                io::stdin().read_line(&mut s)?;
+                let kind = s.trim().parse::<VariableType>();
+                s.clear();
+                let kind = match kind {
+                    Ok(kind) => kind,
+                    Err(e) => {
+                        println!("{e}");
+                        continue;
+                    }
+                };
+
+                // the default written in the template has to match the type
+                match inline_default.as_deref().map(|d| check_default(&kind, d)) {
+                    Some(Err(e)) => println!("the default for {} doesn't fit: {e}", v.variable),
+                    Some(Ok(default)) => {
+                        v.default = default;
+                        break kind;
+                    }
+                    None => break kind,
+                }
+            };
         }
-        s.clear();
-
-        print!(
-            "description value for {} (leave empty for no description): ",
-            v.variable
-        );
-        io::stdout().flush()?;
-        io::stdin().read_line(&mut s)?;
-        let desc = escape(s.trim());
-        if !desc.is_empty() {
-            v.description = Some(desc);
+
+        if inline_default.is_none() {
+            v.default = loop {
+                print!(
+                    "default value for {} (leave empty for no default): ",
+                    v.variable
+                );
+                io::stdout().flush()?;
+                io::stdin().read_line(&mut s)?;
+                let default = s.trim().to_string();
+                s.clear();
+                match check_default(&v.kind, &default) {
+                    Ok(default) => break default,
+                    Err(e) => println!("{e}"),
+                }
+            };
+        }
+
+        if v.description.is_none() {
+            print!(
+                "description value for {} (leave empty for no description): ",
+                v.variable
+            );
+            io::stdout().flush()?;
+            io::stdin().read_line(&mut s)?;
+            let desc = s.trim();
+            if !desc.is_empty() {
+                v.description = Some(desc.to_string());
+            }
+            s.clear();
         }
-        s.clear();
     }
 
     Ok(())
 }
+
+/// checks a default typed in by the user
+///
+/// bools only keep `true` as a default, since false is the same as not set
+pub fn check_default(kind: &VariableType, default: &str) -> Result<Option<String>> {
+    if default.is_empty() {
+        return Ok(None);
+    }
+
+    kind.check(default)?;
+    if *kind == VariableType::Bool && default == "false" {
+        return Ok(None);
+    }
+
+    Ok(Some(default.to_string()))
+}
@@ -1,47 +1,123 @@
-use crate::helpers::escape;
+use crate::{
+    create_template::{apply_vars, check_default},
+    filters::*,
+    helpers::edit_until_valid,
+};
 
 use super::*;
-use serde_derive::Deserialize;
-use std::{collections::HashMap, process::Command};
 
 pub fn edit_template(name: String) -> Result<()> {
-    let path = format!(".moho/{name}.mh");
-    let old_template = String::from_utf8(
-        Command::new("/bin/sh")
-            .args(["-c", &format!("{path} get-template")])
-            .output()?
-            .stdout,
-    )?;
-
-    let mut variables: Vars = toml::from_slice(
-        &Command::new("/bin/sh")
-            .args(["-c", &format!("{path} get-vars")])
-            .output()?
-            .stdout,
-    )?;
-
-    // Command::output adds an extra newline, so we remove it
-    let template = edit::edit(&old_template[0..old_template.len() - 1])?;
-    let mut parsed = parse_template(&template)?;
-
-    ask_defaults_and_descriptions(&mut parsed, &mut variables)?;
-
-    let out = generate_bash_script(&name, parsed, variables.default_path);
-
-    // save to file
-    let out_path = format!(".moho/{name}.mh");
-    std::fs::create_dir_all(".moho")?;
-    let mut file = File::create(&out_path)?;
-    file.write_all(out.as_bytes())?;
-    crate::helpers::make_executable(&out_path)?;
+    let Script {
+        template: old_template,
+        vars: mut variables,
+        status,
+    } = Script::read(&name)?;
+    if let Some(warning) = status.warning(&name) {
+        eprintln!("warning: {warning}");
+    }
 
-    Ok(())
+    let delimiters = variables.delimiters()?;
+    let old_default_path = variables.default_path.clone();
+
+    let custom_filters = read_custom_filters()?;
+    let custom_filters = parse_custom_filters(&custom_filters);
+
+    let template = edit_until_valid(&old_template, |t| {
+        let partials = Partials::load(t, &delimiters, read_partial)?;
+        let parsed = parse_template(t, &delimiters, &partials)?;
+        validate_filters(&parsed, &custom_filters)
+    })?;
+    let partials = Partials::load(&template, &delimiters, read_partial)?;
+    let mut parsed = parse_template(&template, &delimiters, &partials)?;
+
+    // templates with front matter already have everything they need
+    let front_matter_path = parsed.front_matter.as_ref().map(|f| f.path.clone());
+    match front_matter_path {
+        Some(Some(path)) => variables.default_path = Some(path),
+        Some(None) => {}
+        // each file of a generator has its own path
+        None if parsed.is_generator() => {}
+        None => ask_default_path(&mut variables)?,
+    }
+    if parsed.is_generator() {
+        variables.default_path = None;
+    }
+
+    let default_path = variables
+        .default_path
+        .as_ref()
+        .map(|p| p.to_string_lossy().into_owned());
+    parse_path(&mut parsed, default_path.as_deref())?;
+    validate_filters(&parsed, &custom_filters)?;
+    if parsed.front_matter.is_none() {
+        ask_defaults_and_descriptions(&mut parsed, &variables)?;
+    } else {
+        let old_default_path = old_default_path.as_ref().map(|p| p.to_string_lossy());
+        let saved = saved_metadata(
+            &old_template,
+            old_default_path.as_deref(),
+            &variables,
+            &parsed,
+        )?;
+        apply_vars(&mut parsed, &saved, &[], &[])?;
+    }
+
+    let out = generate_bash_script(&name, parsed, &custom_filters);
+
+    crate::helpers::write_template(&name, &out)
 }
 
-fn ask_defaults_and_descriptions(t: &mut Template, vars: &mut Vars) -> Result<()> {
+/// the metadata saved in the script that didn't come from the template itself, like the
+/// defaults given with `--default` when it was created
+///
+/// the rest comes from the template, so editing the front matter can still change or remove it,
+/// and what the edited template sets itself wins
+fn saved_metadata(
+    old_template: &str,
+    old_default_path: Option<&str>,
+    vars: &Vars,
+    new: &Template,
+) -> Result<Vars> {
+    let delimiters = vars.delimiters()?;
+    let partials = Partials::load(old_template, &delimiters, read_partial)?;
+    let mut old = parse_template(old_template, &delimiters, &partials)?;
+    parse_path(&mut old, old_default_path)?;
+
+    // the edited template wins, and variables that were removed don't need anything
+    let saved = |name: &str, field: fn(&Variable) -> Option<String>, value: &String| {
+        let Some(v) = new.variables.iter().find(|v| v.variable == name) else {
+            return false;
+        };
+        let old_value = old.variables.iter().find(|v| v.variable == name);
+        field(v).is_none() && old_value.and_then(field).as_ref() != Some(value)
+    };
+    let kind = |v: &Variable| Some(v.kind.to_string()).filter(|_| v.kind != VariableType::String);
+
+    let mut metadata = Vars::default();
+    for (name, value) in &vars.types {
+        if saved(name, kind, value) {
+            metadata.types.insert(name.clone(), value.clone());
+        }
+    }
+    for (name, default) in &vars.defaults {
+        if saved(name, |v| v.default.clone(), default) {
+            metadata.defaults.insert(name.clone(), default.clone());
+        }
+    }
+    for (name, description) in &vars.descriptions {
+        if saved(name, |v| v.description.clone(), description) {
+            metadata
+                .descriptions
+                .insert(name.clone(), description.clone());
+        }
+    }
+
+    Ok(metadata)
+}
+
+fn ask_default_path(vars: &mut Vars) -> Result<()> {
     let mut rl = rustyline::Editor::<()>::new()?;
 
-    // default path
     let prompt = "default path (leave empty for no default path): ";
     let readline = if let Some(d) = &vars.default_path {
         rl.readline_with_initial(prompt, (&d.to_string_lossy(), ""))
@@ -55,52 +131,86 @@ fn ask_defaults_and_descriptions(t: &mut Template, vars: &mut Vars) -> Result<()
         vars.default_path = Some(readline.into());
     }
 
+    Ok(())
+}
+
+fn ask_defaults_and_descriptions(t: &mut Template, vars: &Vars) -> Result<()> {
+    let mut rl = rustyline::Editor::<()>::new()?;
+
     // vars
     for v in &mut t.variables {
-        if v.variable == "name" {
+        // variables with everything written in the template don't need any questions
+        if v.variable == "name" || (v.default.is_some() && v.description.is_some()) {
             continue;
         }
 
         let prompt = format!(
-            "default value for {} (leave empty for no default): ",
+            "type of {} (string, bool, integer, or choices like `a|b`, leave empty for string): ",
             v.variable
         );
-
-        let readline = if let Some(d) = vars.defaults.get(v.variable) {
-            rl.readline_with_initial(&prompt, (d, ""))
-        } else {
-            rl.readline(&prompt)
-        }
-        .unwrap_or_default();
-
-        let default = escape(readline.trim());
-        if !default.is_empty() {
-            v.default = Some(default);
+        let inline_default = v.default.take();
+        let mut initial = vars.types.get(v.variable).cloned().unwrap_or_default();
+        v.kind = loop {
+            let readline = rl
+                .readline_with_initial(&prompt, (&initial, ""))
+                .unwrap_or_default();
+            let kind = readline.trim().parse::<VariableType>();
+            initial = readline;
+            let kind = match kind {
+                Ok(kind) => kind,
+                Err(e) => {
+                    println!("{e}");
+                    continue;
+                }
+            };
+
+            // the default written in the template has to match the type
+            match inline_default.as_deref().map(|d| check_default(&kind, d)) {
+                Some(Err(e)) => println!("the default for {} doesn't fit: {e}", v.variable),
+                Some(Ok(default)) => {
+                    v.default = default;
+                    break kind;
+                }
+                None => break kind,
+            }
+        };
+
+        if inline_default.is_none() {
+            let prompt = format!(
+                "default value for {} (leave empty for no default): ",
+                v.variable
+            );
+            let mut initial = vars.defaults.get(v.variable).cloned().unwrap_or_default();
+            v.default = loop {
+                let readline = rl
+                    .readline_with_initial(&prompt, (&initial, ""))
+                    .unwrap_or_default();
+                match check_default(&v.kind, readline.trim()) {
+                    Ok(default) => break default,
+                    Err(e) => println!("{e}"),
+                }
+                initial = readline;
+            };
         }
 
-        let prompt = format!(
-            "description value for {} (leave empty for no description): ",
-            v.variable
-        );
-
-        let readline = if let Some(d) = vars.descriptions.get(v.variable) {
-            rl.readline_with_initial(&prompt, (d, ""))
-        } else {
-            rl.readline(&prompt)
-        }
-        .unwrap_or_default();
-        let desc = escape(readline.trim());
-        if !desc.is_empty() {
-            v.description = Some(desc);
+        if v.description.is_none() {
+            let prompt = format!(
+                "description value for {} (leave empty for no description): ",
+                v.variable
+            );
+
+            let readline = if let Some(d) = vars.descriptions.get(v.variable) {
+                rl.readline_with_initial(&prompt, (d, ""))
+            } else {
+                rl.readline(&prompt)
+            }
+            .unwrap_or_default();
+            let desc = readline.trim();
+            if !desc.is_empty() {
+                v.description = Some(desc.to_string());
+            }
         }
     }
 
     Ok(())
 }
-
-#[derive(Deserialize)]
-struct Vars {
-    default_path: Option<PathBuf>,
-    defaults: HashMap<String, String>,
-    descriptions: HashMap<String, String>,
-}
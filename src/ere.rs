@@ -0,0 +1,244 @@
+use super::*;
+use regex::bytes::{Regex, RegexBuilder};
+
+/// a posix extended regex, as matched by the `awk` of the scripts that inject
+///
+/// only the parts that every awk agrees on are allowed, so `moho run` matches the same lines
+/// as the script: no intervals like `{2}`, no escapes like `\w`, and no backslashes in
+/// brackets. lines are matched byte by byte, like in the C locale
+#[derive(Debug)]
+pub struct Ere {
+    regex: Regex,
+}
+
+/// characters that are special outside of brackets, so they can be escaped
+const SPECIAL: &[u8] = b".[]()*+?{}|^$\\/";
+
+/// the classes that can be used in brackets, like `[:alpha:]`
+const CLASSES: &[&str] = &[
+    "alpha", "digit", "alnum", "upper", "lower", "space", "blank", "punct", "print", "graph",
+    "cntrl", "xdigit",
+];
+
+impl Ere {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        if !pattern.is_ascii() {
+            return Err(eyre!("patterns can only have ascii characters"));
+        }
+
+        let mut parser = EreParser {
+            pattern: pattern.as_bytes(),
+            position: 0,
+            regex: String::new(),
+        };
+        parser.alternatives(0)?;
+        if parser.position < parser.pattern.len() {
+            return Err(eyre!("found `)` without a matching `(`"));
+        }
+
+        // without unicode, classes and `.` match single bytes like in the C locale
+        let regex = RegexBuilder::new(&parser.regex)
+            .unicode(false)
+            .build()
+            .map_err(|e| eyre!("invalid regex: {e}"))?;
+        Ok(Self { regex })
+    }
+
+    /// whether any part of the line matches, like awk's `$0 ~ pattern`
+    pub fn is_match(&self, line: &str) -> bool {
+        self.regex.is_match(line.as_bytes())
+    }
+}
+
+/// what came last in a sequence, to know what can be repeated
+#[derive(Clone, Copy)]
+enum Last {
+    Nothing,
+    Anchor,
+    Repeat,
+    Atom,
+}
+
+/// checks the pattern, writing it in the syntax of the regex crate
+struct EreParser<'a> {
+    pattern: &'a [u8],
+    position: usize,
+    regex: String,
+}
+
+impl EreParser<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.pattern.get(self.position).copied()
+    }
+
+    /// returns whether any of the alternatives has something in it
+    fn alternatives(&mut self, depth: usize) -> Result<bool> {
+        let mut empty = vec![self.sequence(depth)?];
+        while self.peek() == Some(b'|') {
+            self.position += 1;
+            self.regex.push('|');
+            empty.push(self.sequence(depth)?);
+        }
+
+        // awks disagree on what empty alternatives match
+        if empty.len() > 1 && empty.iter().any(|&e| e) {
+            return Err(eyre!("`|` can't have an empty side"));
+        }
+        Ok(!empty[0])
+    }
+
+    /// returns whether the sequence is empty
+    fn sequence(&mut self, depth: usize) -> Result<bool> {
+        let mut last = Last::Nothing;
+        let mut empty = true;
+        while let Some(c) = self.peek() {
+            if c == b'|' || (c == b')' && depth > 0) {
+                break;
+            }
+            self.position += 1;
+            empty = false;
+
+            last = match c {
+                b'(' => {
+                    self.regex.push_str("(?:");
+                    let any = self.alternatives(depth + 1)?;
+                    if self.peek() != Some(b')') {
+                        return Err(eyre!("found `(` without a matching `)`"));
+                    }
+                    self.position += 1;
+                    if !any {
+                        return Err(eyre!("groups can't be empty"));
+                    }
+                    self.regex.push(')');
+                    Last::Atom
+                }
+                b')' => return Err(eyre!("found `)` without a matching `(`")),
+                b'*' | b'+' | b'?' => {
+                    match last {
+                        Last::Nothing | Last::Anchor => {
+                            return Err(eyre!("`{}` has nothing to repeat", c as char))
+                        }
+                        Last::Repeat => {
+                            return Err(eyre!(
+                                "`{}` can't repeat a repetition, use a group like `(a*)*`",
+                                c as char
+                            ))
+                        }
+                        Last::Atom => {}
+                    }
+                    self.regex.push(c as char);
+                    Last::Repeat
+                }
+                b'{' | b'}' => {
+                    return Err(eyre!(
+                        "intervals like `{{2}}` aren't supported by every awk, \
+                         use `\\{{` for a literal `{{`"
+                    ))
+                }
+                b'.' | b'^' | b'$' => {
+                    self.regex.push(c as char);
+                    if c == b'.' {
+                        Last::Atom
+                    } else {
+                        Last::Anchor
+                    }
+                }
+                b'[' => {
+                    self.bracket()?;
+                    Last::Atom
+                }
+                b'\\' => match self.peek() {
+                    Some(c) if SPECIAL.contains(&c) => {
+                        self.position += 1;
+                        self.literal(c);
+                        Last::Atom
+                    }
+                    Some(c) => {
+                        return Err(eyre!(
+                            "`\\{}` isn't part of posix extended regexes, which awk uses",
+                            c as char
+                        ))
+                    }
+                    None => return Err(eyre!("patterns can't end with `\\`")),
+                },
+                c => {
+                    self.literal(c);
+                    Last::Atom
+                }
+            };
+        }
+
+        Ok(empty)
+    }
+
+    /// a byte that's matched as is
+    fn literal(&mut self, c: u8) {
+        if c.is_ascii_alphanumeric() {
+            self.regex.push(c as char);
+        } else {
+            self.regex.push_str(&format!("\\x{c:02x}"));
+        }
+    }
+
+    /// a bracket expression like `[^a-z_]`, after its `[`
+    ///
+    /// every item is written as a range of bytes or a class, since the regex crate gives a
+    /// meaning to things like `[a[b]` or `[a&&b]` that awk doesn't
+    fn bracket(&mut self) -> Result<()> {
+        self.regex.push('[');
+        if self.peek() == Some(b'^') {
+            self.position += 1;
+            self.regex.push('^');
+        }
+
+        let mut first = true;
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(eyre!("found `[` without a matching `]`"));
+            };
+            self.position += 1;
+
+            match c {
+                // a `]` right after the `[` is part of the bracket
+                b']' if !first => break,
+                b'\\' => {
+                    return Err(eyre!(
+                        "`\\` can't be used in brackets, since awks disagree on what it means"
+                    ))
+                }
+                b'[' if matches!(self.peek(), Some(b'.' | b'=')) => {
+                    return Err(eyre!("collating elements like `[.a.]` aren't supported"))
+                }
+                b'[' if self.peek() == Some(b':') => {
+                    let rest = &self.pattern[self.position + 1..];
+                    let Some(end) = rest.windows(2).position(|w| w == b":]") else {
+                        return Err(eyre!("found `[:` without a matching `:]`"));
+                    };
+                    let name = String::from_utf8_lossy(&rest[..end]);
+                    if !CLASSES.contains(&&*name) {
+                        return Err(eyre!("`[:{name}:]` isn't a character class"));
+                    }
+                    self.regex.push_str(&format!("[:{name}:]"));
+                    self.position += end + 3;
+                }
+                c if self.peek() == Some(b'-')
+                    && self.pattern.get(self.position + 1) != Some(&b']') =>
+                {
+                    let Some(&end) = self.pattern.get(self.position + 1) else {
+                        return Err(eyre!("found `[` without a matching `]`"));
+                    };
+                    if end < c {
+                        return Err(eyre!("`{}-{}` is an invalid range", c as char, end as char));
+                    }
+                    self.regex.push_str(&format!("\\x{c:02x}-\\x{end:02x}"));
+                    self.position += 2;
+                }
+                c => self.regex.push_str(&format!("\\x{c:02x}")),
+            }
+            first = false;
+        }
+
+        self.regex.push(']');
+        Ok(())
+    }
+}
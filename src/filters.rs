@@ -1,10 +1,700 @@
-pub fn get_filters(_filters: &[&str]) -> String {
-    // TODO properly implement this
-    // not sure if we wanna have like a stdlib or smth
+use super::*;
+use std::path::Path;
 
-    r#"upper() {
-  echo $(echo "$1" | tr '[:lower:]' '[:upper:]')
+/// a filter implemented as a portable sh function
+pub struct Filter {
+    pub name: &'static str,
+    /// arguments the filter takes, after the value
+    arguments: &'static [Argument],
+    /// helper functions this filter calls
+    requires: &'static [&'static str],
+    code: &'static str,
+    /// the same filter in rust, used by `moho run`. the arguments were already validated
+    pub apply: fn(&str, &[FilterArgument]) -> String,
 }
-"#
-    .to_string()
+
+struct Argument {
+    name: &'static str,
+    integer: bool,
+    optional: bool,
+}
+
+impl Argument {
+    const fn string(name: &'static str) -> Self {
+        Self {
+            name,
+            integer: false,
+            optional: false,
+        }
+    }
+
+    const fn integer(name: &'static str) -> Self {
+        Self {
+            name,
+            integer: true,
+            optional: false,
+        }
+    }
+
+    const fn optional(self) -> Self {
+        Self {
+            optional: true,
+            ..self
+        }
+    }
+}
+
+/// functions used by the built-in filters, which can't be used as filters themselves
+const HELPERS: &[(&str, &str)] = &[(
+    "_moho_words",
+    // splits camelCase, PascalCase, snake_case, kebab-case and spaced text
+    // into lowercase words separated by single spaces
+    r#"_moho_words() {
+  printf '%s\n' "$1" \
+    | sed -e 's/\([a-z0-9]\)\([A-Z]\)/\1 \2/g' \
+          -e 's/\([A-Z]\)\([A-Z][a-z]\)/\1 \2/g' \
+          -e 's/[^A-Za-z0-9][^A-Za-z0-9]*/ /g' \
+          -e 's/^ //' -e 's/ $//' \
+    | tr '[:upper:]' '[:lower:]'
+}
+"#,
+)];
+
+pub const BUILTIN_FILTERS: &[Filter] = &[
+    Filter {
+        name: "lower",
+        arguments: &[],
+        requires: &[],
+        code: r#"lower() {
+  printf '%s\n' "$1" | tr '[:upper:]' '[:lower:]'
+}
+"#,
+        apply: |v, _| v.to_ascii_lowercase(),
+    },
+    Filter {
+        name: "upper",
+        arguments: &[],
+        requires: &[],
+        code: r#"upper() {
+  printf '%s\n' "$1" | tr '[:lower:]' '[:upper:]'
+}
+"#,
+        apply: |v, _| v.to_ascii_uppercase(),
+    },
+    Filter {
+        name: "snake",
+        arguments: &[],
+        requires: &["_moho_words"],
+        code: r#"snake() {
+  _moho_words "$1" | tr ' ' '_'
+}
+"#,
+        apply: |v, _| per_line(v, |l| words(l).join("_")),
+    },
+    Filter {
+        name: "camel",
+        arguments: &[],
+        requires: &["_moho_words"],
+        code: r#"camel() {
+  _moho_words "$1" | awk '{ printf "%s", $1; for (i = 2; i <= NF; i++) printf "%s", toupper(substr($i, 1, 1)) substr($i, 2); print "" }'
+}
+"#,
+        apply: |v, _| {
+            per_line(v, |l| {
+                let words = words(l);
+                let mut out = words.first().cloned().unwrap_or_default();
+                out.extend(words.iter().skip(1).map(|w| capitalize(w)));
+                out
+            })
+        },
+    },
+    Filter {
+        name: "pascal",
+        arguments: &[],
+        requires: &["_moho_words"],
+        code: r#"pascal() {
+  _moho_words "$1" | awk '{ for (i = 1; i <= NF; i++) printf "%s", toupper(substr($i, 1, 1)) substr($i, 2); print "" }'
+}
+"#,
+        apply: |v, _| per_line(v, |l| words(l).iter().map(|w| capitalize(w)).collect()),
+    },
+    Filter {
+        name: "kebab",
+        arguments: &[],
+        requires: &["_moho_words"],
+        code: r#"kebab() {
+  _moho_words "$1" | tr ' ' '-'
+}
+"#,
+        apply: |v, _| per_line(v, |l| words(l).join("-")),
+    },
+    Filter {
+        name: "screaming_snake",
+        arguments: &[],
+        requires: &["_moho_words"],
+        code: r#"screaming_snake() {
+  _moho_words "$1" | tr ' ' '_' | tr '[:lower:]' '[:upper:]'
+}
+"#,
+        apply: |v, _| per_line(v, |l| words(l).join("_").to_ascii_uppercase()),
+    },
+    Filter {
+        name: "title",
+        arguments: &[],
+        requires: &["_moho_words"],
+        code: r#"title() {
+  _moho_words "$1" | awk '{ for (i = 1; i <= NF; i++) $i = toupper(substr($i, 1, 1)) substr($i, 2); print }'
+}
+"#,
+        apply: |v, _| {
+            per_line(v, |l| {
+                let words = words(l).iter().map(|w| capitalize(w)).collect::<Vec<_>>();
+                words.join(" ")
+            })
+        },
+    },
+    Filter {
+        name: "capitalize",
+        arguments: &[],
+        requires: &[],
+        code: r#"capitalize() {
+  printf '%s\n' "$1" | awk '{ print toupper(substr($0, 1, 1)) substr($0, 2) }'
+}
+"#,
+        apply: |v, _| per_line(v, capitalize),
+    },
+    Filter {
+        name: "plural",
+        arguments: &[],
+        requires: &[],
+        code: r#"plural() {
+  printf '%s\n' "$1" | sed \
+    -e 's/\([^aeiouAEIOU]\)y$/\1ies/' -e t \
+    -e 's/\([sxz]\)$/\1es/' -e t \
+    -e 's/\([cs]h\)$/\1es/' -e t \
+    -e 's/$/s/'
+}
+"#,
+        apply: |v, _| per_line(v, plural),
+    },
+    Filter {
+        name: "singular",
+        arguments: &[],
+        requires: &[],
+        code: r#"singular() {
+  printf '%s\n' "$1" | sed \
+    -e 's/\([^aeiouAEIOU]\)ies$/\1y/' -e t \
+    -e 's/\(ss\)es$/\1/' -e t \
+    -e 's/\([xz]\)es$/\1/' -e t \
+    -e 's/\([cs]h\)es$/\1/' -e t \
+    -e '/ss$/b' \
+    -e 's/s$//'
+}
+"#,
+        apply: |v, _| per_line(v, singular),
+    },
+    Filter {
+        name: "trim",
+        arguments: &[],
+        requires: &[],
+        code: r#"trim() {
+  printf '%s\n' "$1" | sed -e 's/^[[:space:]]*//' -e 's/[[:space:]]*$//'
+}
+"#,
+        apply: |v, _| {
+            per_line(v, |l| {
+                l.trim_matches(|c: char| c.is_ascii_whitespace() || c == '\x0b')
+                    .to_string()
+            })
+        },
+    },
+    Filter {
+        name: "replace",
+        arguments: &[Argument::string("from"), Argument::string("to")],
+        requires: &[],
+        code: r#"replace() {
+  if [ -z "$2" ]; then
+    printf '%s\n' "$1"
+    return
+  fi
+  rest=$1
+  result=''
+  while :; do
+    case "$rest" in
+      *"$2"*)
+        result="$result${rest%%"$2"*}$3"
+        rest=${rest#*"$2"}
+        ;;
+      *)
+        break
+        ;;
+    esac
+  done
+  printf '%s\n' "$result$rest"
+}
+"#,
+        apply: |v, args| match (string(args, 0), string(args, 1)) {
+            ("", _) => v.to_string(),
+            (from, to) => v.replace(from, to),
+        },
+    },
+    Filter {
+        name: "prefix",
+        arguments: &[Argument::string("prefix")],
+        requires: &[],
+        code: r#"prefix() {
+  printf '%s%s\n' "$2" "$1"
+}
+"#,
+        apply: |v, args| format!("{}{v}", string(args, 0)),
+    },
+    Filter {
+        name: "suffix",
+        arguments: &[Argument::string("suffix")],
+        requires: &[],
+        code: r#"suffix() {
+  printf '%s%s\n' "$1" "$2"
+}
+"#,
+        apply: |v, args| format!("{v}{}", string(args, 0)),
+    },
+    Filter {
+        name: "default",
+        arguments: &[Argument::string("default")],
+        requires: &[],
+        code: r#"default() {
+  if [ -z "$1" ]; then
+    printf '%s\n' "$2"
+  else
+    printf '%s\n' "$1"
+  fi
+}
+"#,
+        apply: |v, args| match v {
+            "" => string(args, 0).to_string(),
+            v => v.to_string(),
+        },
+    },
+    Filter {
+        name: "truncate",
+        arguments: &[Argument::integer("length")],
+        requires: &[],
+        code: r#"truncate() {
+  printf '%s\n' "$1" | awk -v n="$2" '{ print substr($0, 1, n) }'
+}
+"#,
+        apply: |v, args| {
+            per_line(v, |l| {
+                l.chars().take(integer(args, 0).max(0) as usize).collect()
+            })
+        },
+    },
+    Filter {
+        name: "pad",
+        arguments: &[
+            Argument::integer("width"),
+            Argument::string("fill").optional(),
+        ],
+        requires: &[],
+        code: r#"pad() {
+  printf '%s\n' "$1" | awk -v n="$2" -v c="${3:- }" '{ s = $0; while (length(s) < n) s = s c; print s }'
+}
+"#,
+        apply: |v, args| {
+            let width = integer(args, 0).max(0) as usize;
+            let fill = match string(args, 1) {
+                "" => " ",
+                fill => fill,
+            };
+            per_line(v, |l| {
+                let mut s = l.to_string();
+                while s.chars().count() < width {
+                    s.push_str(fill);
+                }
+                s
+            })
+        },
+    },
+];
+
+/// runs a line based filter on every line of the value, like sed and awk do
+fn per_line(value: &str, f: impl Fn(&str) -> String) -> String {
+    value.split('\n').map(f).collect::<Vec<_>>().join("\n")
+}
+
+fn string(args: &[FilterArgument], i: usize) -> &str {
+    match args.get(i) {
+        Some(FilterArgument::String(s)) => s,
+        _ => "",
+    }
+}
+
+fn integer(args: &[FilterArgument], i: usize) -> i64 {
+    match args.get(i) {
+        Some(FilterArgument::Integer(i)) => *i,
+        _ => 0,
+    }
+}
+
+/// same as `_moho_words`
+fn words(line: &str) -> Vec<String> {
+    let chars = line.chars().collect::<Vec<_>>();
+    let is = |i: usize, f: fn(&char) -> bool| chars.get(i).is_some_and(f);
+
+    // `aB` -> `a B`, and `ABc` -> `A Bc`, without overlapping, like sed's `g`
+    let mut split = Vec::with_capacity(chars.len());
+    let mut i = 0;
+    while i < chars.len() {
+        if (is(i, char::is_ascii_lowercase) || is(i, char::is_ascii_digit))
+            && is(i + 1, char::is_ascii_uppercase)
+        {
+            split.extend([chars[i], ' ', chars[i + 1]]);
+            i += 2;
+        } else {
+            split.push(chars[i]);
+            i += 1;
+        }
+    }
+
+    let mut spaced = Vec::with_capacity(split.len());
+    let mut i = 0;
+    let is = |i: usize, f: fn(&char) -> bool| split.get(i).is_some_and(f);
+    while i < split.len() {
+        if is(i, char::is_ascii_uppercase)
+            && is(i + 1, char::is_ascii_uppercase)
+            && is(i + 2, char::is_ascii_lowercase)
+        {
+            spaced.extend([split[i], ' ', split[i + 1], split[i + 2]]);
+            i += 3;
+        } else {
+            spaced.push(split[i]);
+            i += 1;
+        }
+    }
+
+    spaced
+        .into_iter()
+        .collect::<String>()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_ascii_lowercase())
+        .collect()
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c.to_ascii_uppercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+fn is_consonant(c: Option<char>) -> bool {
+    c.is_some_and(|c| !"aeiouAEIOU".contains(c))
+}
+
+/// same rules as the `plural` sh function
+fn plural(word: &str) -> String {
+    if let Some(stem) = word.strip_suffix('y') {
+        if is_consonant(stem.chars().next_back()) {
+            return format!("{stem}ies");
+        }
+    }
+
+    if word.ends_with(['s', 'x', 'z']) || word.ends_with("ch") || word.ends_with("sh") {
+        format!("{word}es")
+    } else {
+        format!("{word}s")
+    }
+}
+
+/// same rules as the `singular` sh function
+fn singular(word: &str) -> String {
+    if let Some(stem) = word.strip_suffix("ies") {
+        if is_consonant(stem.chars().next_back()) {
+            return format!("{stem}y");
+        }
+    }
+
+    if let Some(stem) = word.strip_suffix("es") {
+        if stem.ends_with("ss")
+            || stem.ends_with(['x', 'z'])
+            || stem.ends_with("ch")
+            || stem.ends_with("sh")
+        {
+            return stem.to_string();
+        }
+    }
+
+    match word.strip_suffix('s') {
+        Some(stem) if !word.ends_with("ss") => stem.to_string(),
+        _ => word.to_string(),
+    }
+}
+
+/// generates the sh functions for the given filters, and the functions they need
+///
+/// custom filters take precedence over built-in filters with the same name
+pub fn get_filters(filters: &[&str], custom: &[CustomFilter]) -> String {
+    let builtin = |name: &str| {
+        BUILTIN_FILTERS
+            .iter()
+            .find(|f| f.name == name && !custom.iter().any(|c| c.name == name))
+    };
+
+    // custom filters might call other functions, so we follow those calls
+    let mut used = filters.to_vec();
+    let mut i = 0;
+    while i < used.len() {
+        if let Some(c) = custom.iter().find(|c| c.name == used[i]) {
+            let called = custom
+                .iter()
+                .map(|f| f.name)
+                .chain(BUILTIN_FILTERS.iter().map(|f| f.name))
+                .filter(|name| *name != c.name && !used.contains(name) && calls(c.code, name))
+                .collect::<Vec<_>>();
+            used.extend(called);
+        }
+        i += 1;
+    }
+
+    let used_builtin = BUILTIN_FILTERS
+        .iter()
+        .filter(|f| used.contains(&f.name) && builtin(f.name).is_some())
+        .collect::<Vec<_>>();
+
+    let mut out = String::new();
+
+    for (name, code) in HELPERS {
+        if used_builtin.iter().any(|f| f.requires.contains(name)) {
+            out.push_str(code);
+        }
+    }
+
+    for f in used_builtin {
+        out.push_str(f.code);
+    }
+
+    for f in custom.iter().filter(|f| used.contains(&f.name)) {
+        out.push_str(f.code);
+        if !f.code.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+
+    out
+}
+
+/// checks if `code` contains `name` as a whole word
+fn calls(code: &str, name: &str) -> bool {
+    let is_ident = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    code.match_indices(name).any(|(i, _)| {
+        !code[..i].chars().next_back().map(is_ident).unwrap_or(false)
+            && !code[i + name.len()..]
+                .chars()
+                .next()
+                .map(is_ident)
+                .unwrap_or(false)
+    })
+}
+
+/// reads the project's custom filters file, if there is one
+pub fn read_custom_filters() -> Result<String> {
+    let path = Path::new(".moho/filters.sh");
+    if path.is_file() {
+        Ok(std::fs::read_to_string(path)?)
+    } else {
+        Ok(String::new())
+    }
+}
+
+/// a function defined in the project's `.moho/filters.sh`
+#[derive(Debug, PartialEq)]
+pub struct CustomFilter<'a> {
+    pub name: &'a str,
+    /// the whole function definition
+    code: &'a str,
+}
+
+/// finds the function definitions in a filters file, like `my_filter() { ... }`
+pub fn parse_custom_filters(source: &str) -> Vec<CustomFilter<'_>> {
+    let mut filters = Vec::new();
+    let mut start = 0;
+
+    while start < source.len() {
+        let line_end = source[start..]
+            .find('\n')
+            .map(|i| start + i + 1)
+            .unwrap_or(source.len());
+        let line = &source[start..line_end];
+
+        let name = line
+            .trim_start()
+            .split_once("()")
+            .map(|(name, _)| name.trim_end())
+            .filter(|name| {
+                !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+            });
+
+        if let Some(name) = name {
+            let code_start = start + line.len() - line.trim_start().len();
+            if let Some(end) = function_end(source, code_start) {
+                filters.push(CustomFilter {
+                    name,
+                    code: &source[code_start..end],
+                });
+                start = end;
+                continue;
+            }
+        }
+
+        start = line_end;
+    }
+
+    filters
+}
+
+/// finds the end of the function body starting at `start`, including the trailing newline
+///
+/// braces inside of quotes and comments are ignored
+fn function_end(source: &str, start: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut comment = false;
+    let mut escaped = false;
+    let mut prev = ' ';
+
+    for (i, c) in source[start..].char_indices() {
+        if comment {
+            comment = c != '\n';
+        } else if escaped {
+            escaped = false;
+        } else if let Some(q) = quote {
+            if c == q {
+                quote = None;
+            } else if c == '\\' && q == '"' {
+                escaped = true;
+            }
+        } else {
+            match c {
+                '\\' => escaped = true,
+                '"' | '\'' => quote = Some(c),
+                '#' if prev.is_whitespace() => comment = true,
+                '{' => depth += 1,
+                '}' if depth > 0 => {
+                    depth -= 1;
+                    if depth == 0 {
+                        let end = start + i + 1;
+                        return Some(
+                            source[end..]
+                                .find('\n')
+                                .map(|n| end + n + 1)
+                                .unwrap_or(source.len()),
+                        );
+                    }
+                }
+                _ => {}
+            }
+        }
+        prev = c;
+    }
+
+    None
+}
+
+/// checks that every filter used in the template is either built-in or a custom filter,
+/// and that built-in filters get the arguments they expect
+pub fn validate_filters(t: &Template, custom: &[CustomFilter]) -> Result<()> {
+    let known = BUILTIN_FILTERS
+        .iter()
+        .map(|f| f.name)
+        .chain(custom.iter().map(|f| f.name))
+        .collect::<Vec<_>>();
+
+    for call in t.filter_calls() {
+        let filter = call.name;
+
+        // custom filters can take any arguments
+        if custom.iter().any(|c| c.name == filter) {
+            continue;
+        }
+
+        if let Some(builtin) = BUILTIN_FILTERS.iter().find(|f| f.name == filter) {
+            validate_arguments(builtin, &call.arguments)?;
+            continue;
+        }
+
+        let closest = known
+            .iter()
+            .map(|k| (distance(filter, k), k))
+            .filter(|(d, _)| *d <= 3)
+            .min_by_key(|(d, _)| *d);
+
+        return Err(match closest {
+            Some((_, k)) => eyre!("unknown filter `{filter}`, did you mean `{k}`?"),
+            None => eyre!("unknown filter `{filter}`"),
+        });
+    }
+
+    Ok(())
+}
+
+fn validate_arguments(filter: &Filter, arguments: &[FilterArgument]) -> Result<()> {
+    let signature = filter
+        .arguments
+        .iter()
+        .map(|a| match (a.integer, a.optional) {
+            (false, false) => format!("{}: string", a.name),
+            (true, false) => format!("{}: integer", a.name),
+            (false, true) => format!("{}?: string", a.name),
+            (true, true) => format!("{}?: integer", a.name),
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let usage = || {
+        if signature.is_empty() {
+            format!("filter `{}` takes no arguments", filter.name)
+        } else {
+            format!(
+                "filter `{}` is used like `{}({signature})`",
+                filter.name, filter.name
+            )
+        }
+    };
+
+    let required = filter.arguments.iter().filter(|a| !a.optional).count();
+    if arguments.len() < required || arguments.len() > filter.arguments.len() {
+        return Err(eyre!("{}", usage()));
+    }
+
+    for (argument, expected) in arguments.iter().zip(filter.arguments) {
+        if expected.integer != matches!(argument, FilterArgument::Integer(_)) {
+            return Err(eyre!("{}", usage()));
+        }
+    }
+
+    Ok(())
+}
+
+/// levenshtein distance between two strings
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut prev = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == *cb {
+                prev
+            } else {
+                1 + prev.min(row[j]).min(row[j + 1])
+            };
+            prev = current;
+        }
+    }
+
+    row[b.len()]
 }
@@ -0,0 +1,168 @@
+use super::*;
+use serde::{de::Error as _, Deserialize, Deserializer};
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+
+/// metadata written at the top of a template, between `+++` lines
+///
+/// ```text
+/// +++
+/// path = "app/Http/Controllers/name.php"
+/// description = "a controller for the given model"
+/// messages = ["remember to add a route for it"]
+///
+/// [variables.namespace]
+/// default = "App\\Http\\Controllers"
+/// description = "controller namespace"
+/// +++
+/// ```
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrontMatter {
+    /// default path the template will output to
+    pub path: Option<PathBuf>,
+    /// shown in the help of the template
+    pub description: Option<String>,
+    /// printed after the template is generated
+    pub messages: Vec<String>,
+    /// adds the output to the existing file at `path`, instead of overwriting it
+    pub inject: Option<Inject>,
+    pub variables: BTreeMap<String, VariableMetadata>,
+}
+
+/// where the output goes in the file, at its end unless `before` or `after` are set
+///
+/// ```text
+/// [inject]
+/// after = "^use "
+/// ```
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Inject {
+    /// extended regex, the output goes before the first line that matches it
+    pub before: Option<String>,
+    /// extended regex, the output goes after the first line that matches it
+    pub after: Option<String>,
+}
+
+impl Inject {
+    /// the regex, and whether the output goes after the line it matches
+    pub fn pattern(&self) -> Option<(&str, bool)> {
+        match (&self.before, &self.after) {
+            (Some(before), _) => Some((before, false)),
+            (None, Some(after)) => Some((after, true)),
+            (None, None) => None,
+        }
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.before.is_some() && self.after.is_some() {
+            return Err(eyre!("`inject` can have `before` or `after`, but not both"));
+        }
+        if let Some((pattern, _)) = self.pattern() {
+            crate::ere::Ere::parse(pattern).map_err(|e| eyre!("invalid regex in `inject`: {e}"))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct VariableMetadata {
+    #[serde(deserialize_with = "deserialize_default")]
+    pub default: Option<String>,
+    pub description: Option<String>,
+    #[serde(rename = "type")]
+    pub kind: Option<VariableType>,
+}
+
+impl FrontMatter {
+    /// reads the front matter of the template, if it has one
+    pub fn parse(template: &str) -> Result<Option<Self>, ParseError> {
+        let Some((source, _)) = split_front_matter(template)? else {
+            return Ok(None);
+        };
+
+        let front_matter: Self = toml::from_str(source).map_err(|e| {
+            // point the error to the line in the template
+            let offset = match e.line_col() {
+                Some((line, column)) => {
+                    let line_start = source
+                        .split_inclusive('\n')
+                        .take(line)
+                        .map(str::len)
+                        .sum::<usize>();
+                    let mut offset =
+                        (FRONT_MATTER_START.len() + line_start + column).min(template.len());
+                    while !template.is_char_boundary(offset) {
+                        offset -= 1;
+                    }
+                    offset
+                }
+                None => 0,
+            };
+            let message = e.to_string();
+            let message = match message.rsplit_once(" at line ") {
+                Some((message, _)) => message.to_string(),
+                None => message,
+            };
+
+            ParseError::new(template, offset, format!("invalid front matter: {message}"))
+        })?;
+
+        if let Some(inject) = &front_matter.inject {
+            inject
+                .check()
+                .map_err(|e| ParseError::new(template, 0, format!("invalid front matter: {e}")))?;
+        }
+
+        Ok(Some(front_matter))
+    }
+}
+
+const FRONT_MATTER_START: &str = "+++\n";
+
+/// splits the front matter from the template, returning its source and where the body starts
+pub fn split_front_matter(template: &str) -> Result<Option<(&str, usize)>, ParseError> {
+    let Some(rest) = template.strip_prefix(FRONT_MATTER_START) else {
+        return Ok(None);
+    };
+
+    let mut line_start = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "+++" {
+            let source = &rest[..line_start];
+            return Ok(Some((
+                source,
+                FRONT_MATTER_START.len() + line_start + line.len(),
+            )));
+        }
+        line_start += line.len();
+    }
+
+    Err(ParseError::new(
+        template,
+        0,
+        "front matter is missing its closing `+++`",
+    ))
+}
+
+/// defaults can be written as strings, integers or bools
+fn deserialize_default<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    match toml::Value::deserialize(d)? {
+        toml::Value::String(s) => Ok(Some(s)),
+        toml::Value::Integer(i) => Ok(Some(i.to_string())),
+        toml::Value::Boolean(b) => Ok(Some(b.to_string())),
+        v => Err(D::Error::custom(format!(
+            "defaults should be strings, integers or bools, found {}",
+            v.type_str()
+        ))),
+    }
+}
+
+impl<'de> Deserialize<'de> for VariableType {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        String::deserialize(d)?.parse().map_err(D::Error::custom)
+    }
+}
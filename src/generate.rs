@@ -1,12 +1,16 @@
 use super::*;
-use crate::{filters::*, helpers::escape};
+use crate::{
+    filters::*,
+    helpers::{escape, quote},
+    script::{add_header, heredoc, TEMPLATE_DELIMITER, VARS_DELIMITER},
+};
 
 // TODO split into functions
 
 pub fn generate_bash_script(
     template_name: &str,
     t: Template,
-    default_path: Option<PathBuf>,
+    custom_filters: &[CustomFilter],
 ) -> String {
     let mut script = String::from(
         r#"#!/bin/sh
@@ -28,8 +32,14 @@ if [ ! "$1" = "get-template" ] && [ ! "$1" = "get-vars" ]; then
 
     // generate variables
     for v in &t.variables {
-        append!(v.variable, "=''\n");
+        // bools can't use `${var:-default}`, since false is empty
+        if v.kind == VariableType::Bool && v.default.as_deref() == Some("true") {
+            append!(v.variable, "='true'\n");
+        } else {
+            append!(v.variable, "=''\n");
+        }
     }
+    append!("_moho_output=''\n_moho_overwrite='ask'\n_moho_interactive=''\n");
 
     // generate argument parsing
     append!(
@@ -40,6 +50,21 @@ while test $# -gt 0; do
 "#,
     );
     for v in &t.variables {
+        if v.kind == VariableType::Bool {
+            append!(
+                "    --",
+                v.variable,
+                ")\n      shift\n      ",
+                v.variable,
+                "='true'\n      ;;\n    --no-",
+                v.variable,
+                ")\n      shift\n      ",
+                v.variable,
+                "=''\n      ;;\n",
+            );
+            continue;
+        }
+
         append!(
             "    --",
             v.variable,
@@ -52,69 +77,33 @@ while test $# -gt 0; do
         );
     }
 
-    // count how many spaces we need
-    let help_len = "-h, --help".len();
-    let name_len = "--name NAME".len();
-    let max = t
-        .variables
-        .iter()
-        // +3 because of the two dashes, and the one space
-        .map(|v| v.variable.len() * 2 + 3)
-        .max()
-        .unwrap_or_default()
-        .max(help_len)
-        .max(name_len)
-        + 5;
-
-    let spaces = (0..max).map(|_| ' ').collect::<String>();
-
-    let path = if let Some(p) = default_path.clone() {
-        if let Some(ext) = p.extension() {
-            p.with_file_name("NAME.meow").with_extension(ext)
-        } else {
-            p.with_file_name("NAME")
-        }
-        .to_string_lossy()
-        .to_string()
-    } else {
-        "./NAME".into()
-    };
-
+    // after the variables, so templates can have variables with the same names
     append!(
-        r#"    *)
-      echo ""#,
-        template_name,
-        r#":"
-      echo "generates file at "#,
-        &path,
-        r#""
-      echo ""
-      echo "options:"
-      echo "-h, --help"#,
-        &spaces[help_len..],
-        r#"show brief help"
-      echo "--name NAME"#,
-        &spaces[name_len..],
-        r#"filename (without extension)"
+        r#"    --force)
+      shift
+      _moho_overwrite='yes'
+      ;;
+    --no-overwrite)
+      shift
+      _moho_overwrite='no'
+      ;;
+    --dry-run)
+      shift
+      _moho_output='dry-run'
+      ;;
+    --stdout)
+      shift
+      _moho_output='stdout'
+      ;;
+    --interactive)
+      shift
+      _moho_interactive='true'
+      ;;
+    *)
 "#
     );
-
-    for v in &t.variables {
-        if v.variable == "name" {
-            continue;
-        }
-
-        append!(
-            r#"      echo "--"#,
-            v.variable,
-            " ",
-            &v.variable.to_uppercase(),
-        );
-        if let Some(desc) = &v.description {
-            let l = v.variable.len() * 2 + 3;
-            append!(&spaces[l..], desc);
-        }
-        append!("\"\n");
+    for line in help(template_name, &t) {
+        append!("      echo \"", &escape(line), "\"\n");
     }
 
     append!(
@@ -125,20 +114,49 @@ done
 "#,
     );
 
+    // the output goes to a file unless it's piped, generators always write their files
+    if t.is_generator() {
+        append!("\n# where the output goes\n_moho_output=${_moho_output:-file}\n");
+    } else {
+        append!(
+            r#"
+# where the output goes, files aren't written when it's piped, unless saying what to do
+# with the existing ones
+if [ -z "$_moho_output" ] ; then
+  if [ -t 1 ] || [ "$_moho_overwrite" != 'ask' ] ; then
+    _moho_output='file'
+  else
+    _moho_output='stdout'
+  fi
+fi
+"#
+        );
+    }
+
+    append!(&generate_prompts(&t));
+
     // defaults if there are any
-    if t.variables.iter().any(|v| v.default.is_some()) {
+    let has_default = |v: &&Variable| v.default.is_some() && v.kind != VariableType::Bool;
+    if t.variables.iter().any(|v| has_default(&v)) {
         append!("\n# set variable defaults\n");
     }
-    for v in &t.variables {
+    for v in t.variables.iter().filter(has_default) {
         if let Some(default) = &v.default {
-            append!(v.variable, "=${", v.variable, ":-\"", default, "\"}\n");
+            append!(
+                v.variable,
+                "=${",
+                v.variable,
+                ":-\"",
+                &escape(default),
+                "\"}\n"
+            );
         }
     }
 
     // check that all variables have values
     append!("\n# check that all variables have values\n");
     for v in &t.variables {
-        if v.variable == "name" {
+        if v.variable == "name" || v.optional || v.kind == VariableType::Bool {
             continue;
         }
         append!(
@@ -150,162 +168,724 @@ done
         );
     }
 
-    // only do the name nullable check if the variable is used,
-    // or if it's not being piped to a file
-    let name_check = if t.is_name_used { "" } else { " && [ -t 1 ]" };
-    append!(
-        r#"if [ -z "$name" ]"#,
-        name_check,
-        r#"; then
+    if let Some(name_check) = name_check(&t) {
+        append!(
+            r#"if [ -z "$name" ]"#,
+            name_check,
+            r#"; then
   echo "Error: No value provided for name"
   exit 1
 fi
 "#
-    );
+        );
+    }
 
-    if !t.filtered.is_empty() {
-        // get all the used filters
-        let filters = get_filters(
-            &t.filtered
-                .iter()
-                .flat_map(|a| a.filters.clone())
-                .collect::<Vec<_>>(),
+    // check that integers and choices have valid values
+    for v in &t.variables {
+        let (pattern, expected) = match &v.kind {
+            VariableType::Integer => ("-|*[!0-9-]*|?*-*)".to_string(), "an integer".to_string()),
+            VariableType::Enum(choices) => (
+                format!(
+                    "''|{}) ;;\n  *)",
+                    choices.iter().map(quote).collect::<Vec<_>>().join("|")
+                ),
+                format!("one of {}", escape(choices.join(", "))),
+            ),
+            _ => continue,
+        };
+
+        append!(
+            "case \"$",
+            v.variable,
+            "\" in\n  ",
+            &pattern,
+            "\n    echo \"Error: ",
+            v.variable,
+            " must be ",
+            &expected,
+            ", got '$",
+            v.variable,
+            "'\"\n    exit 1\n    ;;\nesac\n"
         );
-        append!("\n# filters\n", &filters);
+    }
 
+    // get all the used filters
+    let filters = t.used_filters();
+    if !filters.is_empty() {
+        append!("\n# filters\n", &get_filters(&filters, custom_filters));
+    }
+
+    if !t.filtered.is_empty() {
         append!("\n# filtered variables\n");
         for v in &t.filtered {
-            append!(&v.name, "=");
-            for filter in &v.filters {
-                append!("$(", filter, " ");
+            append!(&v.name, "=", &filtered_expression(v), "\n");
+        }
+    }
+    if t.is_generator() {
+        append!(&generate_files(&t.files));
+    } else {
+        append!("\n", &generate_output(&t.nodes));
+        match t.front_matter.as_ref().and_then(|f| f.inject.as_ref()) {
+            Some(inject) => {
+                append!(&generate_inject(&t.path, inject));
             }
-            append!("\"$", v.variable, "\"");
-            for _ in &v.filters {
-                append!(")");
+            None => {
+                append!(&generate_file(&t.path));
             }
-            append!("\n");
         }
     }
-    append!("\nout=\"", &t.generated, "\"\n");
 
-    let mkdir = if let Some(p) = &default_path {
-        if let Some(p) = p.parent() {
-            format!(
-                r#"  mkdir -p "{}"
-"#,
-                escape(p.to_string_lossy())
-            )
-        } else {
-            "".to_string()
+    // messages for after the template is generated, they go to stderr so they're not
+    // mixed with the output
+    let messages = t
+        .front_matter
+        .as_ref()
+        .map(|f| &f.messages[..])
+        .unwrap_or_default();
+    if !messages.is_empty() {
+        append!("\n# messages\n");
+        for message in messages {
+            append!("printf '%s\\n' \"", &escape(message), "\" >&2\n");
         }
+    }
+
+    // edit
+    append!(
+        r#"
+# end normal block
+fi
+
+# template editing section, read by `moho edit` without running the script
+
+if [ "$1" = "get-template" ]; then
+"#,
+        &heredoc(TEMPLATE_DELIMITER, t.original),
+        r#"fi
+
+if [ "$1" = "get-vars" ]; then
+"#,
+        &heredoc(VARS_DELIMITER, &generate_vars(&t)),
+        "fi\n"
+    );
+
+    add_header(&script)
+}
+
+/// only do the name nullable check if the variable is used, or if the path needs it and the
+/// output isn't being printed. generators always need their paths
+fn name_check(t: &Template) -> Option<&'static str> {
+    if t.is_name_used || (t.is_generator() && t.path_uses_name()) {
+        Some("")
+    } else if t.path_uses_name() {
+        Some(r#" && [ "$_moho_output" != 'stdout' ]"#)
     } else {
-        "".to_string()
-    };
+        None
+    }
+}
 
-    let path = if let Some(p) = &default_path {
-        // we first write the name variable as `#{name}`, then escape all characters ($ included),
-        // then we change the `#` to a `$`
-        // this is definitely not the best way to do this but im not sure what to do
-        let s = if let Some(ext) = p.extension() {
-            p.with_file_name("#{name}.meow").with_extension(ext)
+/// asks for the values that are missing when running on a terminal, and for every value with
+/// `--interactive`. an empty answer keeps the value, or the default
+fn generate_prompts(t: &Template) -> String {
+    let mut prompts = String::new();
+    for v in &t.variables {
+        let missing = if v.variable == "name" {
+            match name_check(t) {
+                Some(check) => Some(format!(r#"[ -z "$name" ]{check} && [ -t 0 ]"#)),
+                // the name isn't used at all
+                None => continue,
+            }
+        } else if v.optional || v.default.is_some() || v.kind == VariableType::Bool {
+            None
         } else {
-            p.with_file_name("#{name}")
+            Some(format!(r#"[ -z "${}" ] && [ -t 0 ]"#, v.variable))
         };
-        escape(s.to_string_lossy()).replace("#{name}", "${name}")
-    } else {
-        "./${name}".into()
-    };
+        let condition = match missing {
+            Some(missing) => format!(r#"[ -n "$_moho_interactive" ] || {{ {missing}; }}"#),
+            None => r#"[ -n "$_moho_interactive" ]"#.to_string(),
+        };
+        let label = escape(prompt(v));
+        let var = v.variable;
+
+        if v.kind == VariableType::Bool {
+            prompts.push_str(&format!(
+                r#"if {condition} ; then
+  if [ -n "${var}" ] ; then _moho_current='y' ; else _moho_current='n' ; fi
+  _moho_ask "{label}" "$_moho_current"
+  case "$_moho_answer" in
+    [yY]|[yY][eE][sS])
+      {var}='true'
+      ;;
+    [nN]|[nN][oO])
+      {var}=''
+      ;;
+  esac
+fi
+"#
+            ));
+            continue;
+        }
 
-    append!(
-        r#"if [ -t 1 ] ; then
-"#,
-        &mkdir,
+        let current = match &v.default {
+            Some(default) => format!("${{{var}:-\"{}\"}}", escape(default)),
+            None => format!("${var}"),
+        };
+        prompts.push_str(&format!(
+            r#"if {condition} ; then
+  _moho_ask "{label}" "{current}"
+  if [ -n "$_moho_answer" ] ; then
+    {var}="$_moho_answer"
+  fi
+fi
+"#
+        ));
+    }
+
+    if prompts.is_empty() {
+        return prompts;
+    }
+    format!(
         r#"
+# ask for missing values on a terminal, or for every value with --interactive
+_moho_ask() {{
+  if [ -n "$2" ] ; then
+    printf '%s [%s]: ' "$1" "$2" >&2
+  else
+    printf '%s: ' "$1" >&2
+  fi
+  read -r _moho_answer || _moho_answer=''
+}}
+{prompts}"#
+    )
+}
+
+/// what a variable is asked with, like `kind (the kind of class, one of model, view)`
+pub fn prompt(v: &Variable) -> String {
+    let mut details = Vec::new();
+    match &v.description {
+        Some(desc) => details.push(desc.clone()),
+        None if v.variable == "name" => details.push("filename without extension".to_string()),
+        None => {}
+    }
+    match &v.kind {
+        VariableType::Bool => details.push("y/n".to_string()),
+        VariableType::Integer => details.push("an integer".to_string()),
+        VariableType::Enum(choices) => details.push(format!("one of {}", choices.join(", "))),
+        VariableType::String => {}
+    }
+
+    if details.is_empty() {
+        v.variable.to_string()
+    } else {
+        format!("{} ({})", v.variable, details.join(", "))
+    }
+}
+
+/// writes `_moho_out` to the path, or prints it when asked to or when the output is piped
+fn generate_file(path: &OutputPath) -> String {
+    format!(
+        r#"_moho_path="{}"
+if [ "$_moho_output" = 'file' ] ; then
+  mkdir -p "$(dirname "$_moho_path")"
+
   # check if file exists
-  if [ -f ""#,
-        &path,
-        r#"" ] ; then
-     read -r -p "File already exists, overwrite? [y/N] " response
-     case "$response" in
-       [yY][eE][sS]|[yY])
-         ;;
-       *)
-         echo "Stopping"
-         exit 1
-         ;;
-     esac
+  if [ -f "$_moho_path" ] ; then
+    case "$_moho_overwrite" in
+      no)
+        echo "Error: $_moho_path already exists"
+        exit 1
+        ;;
+      ask)
+        read -r -p "File already exists, overwrite? [y/N] " response
+        case "$response" in
+          [yY][eE][sS]|[yY])
+            ;;
+          *)
+            echo "Stopping"
+            exit 1
+            ;;
+        esac
+        ;;
+    esac
   fi
 
-  echo "$out" > ""#,
-        &path,
-        r#""
-  echo "created file at "#,
-        &path,
-        r#"";
+  printf '%s' "$_moho_out" > "$_moho_path"
+  echo "created file at $_moho_path";
+elif [ "$_moho_output" = 'dry-run' ] ; then
+  echo "would write to $_moho_path:"
+  printf '%s\n' "$(printf '%s' "$_moho_out")"
 else
-  echo "$out"
+  printf '%s' "$_moho_out"
 fi
-"#
-    );
+"#,
+        path_expression(path)
+    )
+}
 
-    // edit
-    append!(
-        r#"
-# end normal block
-fi
+/// adds `_moho_out` to the existing file at the path unless the file already has it, or prints
+/// it when asked to or when the output is piped
+fn generate_inject(path: &OutputPath, inject: &Inject) -> String {
+    // awk matches extended regexes, like `grep -E`
+    let insert = match inject.pattern() {
+        Some((pattern, after)) => {
+            let print = if after {
+                "{ print } !done && $0 ~ ENVIRON[\"_moho_pattern\"] { print ENVIRON[\"_moho_added\"]; done = 1 }"
+            } else {
+                "!done && $0 ~ ENVIRON[\"_moho_pattern\"] { print ENVIRON[\"_moho_added\"]; done = 1 } { print }"
+            };
+            format!(
+                r#"        _moho_tmp="${{TMPDIR:-/tmp}}/moho.$$"
+        if _moho_pattern={pattern} _moho_added="$_moho_added" \
+          awk '{print} END {{ exit !done }}' "$_moho_path" > "$_moho_tmp" ; then
+          cat "$_moho_tmp" > "$_moho_path"
+          rm -f "$_moho_tmp"
+        else
+          rm -f "$_moho_tmp"
+          echo "Error: no line in $_moho_path matches '{escaped}'"
+          exit 1
+        fi
+"#,
+                pattern = quote(pattern),
+                escaped = escape(pattern),
+            )
+        }
+        None => r#"        # the file might not end with a newline
+        if [ -n "$(tail -c 1 "$_moho_path")" ] ; then
+          echo >> "$_moho_path"
+        fi
+        printf '%s\n' "$_moho_added" >> "$_moho_path"
+"#
+        .to_string(),
+    };
 
-# template editing section
+    format!(
+        r#"_moho_path="{}"
+if [ "$_moho_output" = 'stdout' ] ; then
+  printf '%s' "$_moho_out"
+else
+  if [ ! -f "$_moho_path" ] ; then
+    echo "Error: $_moho_path doesn't exist"
+    exit 1
+  fi
 
-if [ "$1" = "get-template" ]; then
-echo ""#,
-        &escape(&t.original),
-        r#""
+  # skip the file if it already has the output
+  _moho_added=$(printf '%s' "$_moho_out")
+  case "$(cat "$_moho_path")" in
+    *"$_moho_added"*)
+      echo "$_moho_path already has it, skipping"
+      ;;
+    *)
+      if [ "$_moho_output" = 'dry-run' ] ; then
+        echo "would inject into $_moho_path:"
+        printf '%s\n' "$_moho_added"
+      else
+{insert}        echo "injected into $_moho_path"
+      fi
+      ;;
+  esac
 fi
+"#,
+        path_expression(path)
+    )
+}
 
-if [ "$1" = "get-vars" ]; then
-echo ""#
-    );
+/// renders every file of a generator, then writes them all, asking once before overwriting
+/// any of them, or prints them when asked to
+fn generate_files(files: &[OutputFile]) -> String {
+    let mut out = String::new();
+    for (i, file) in files.iter().enumerate() {
+        if let Some(source) = file.path.source {
+            out.push_str(&format!("\n# {}\n", source.replace('\n', " ")));
+        }
+        out.push_str(&generate_output(&file.nodes));
+        out.push_str(&format!(
+            "_moho_file_{i}=\"$_moho_out\"\n_moho_path_{i}=\"{}\"\n",
+            path_expression(&file.path)
+        ));
+    }
 
-    if let Some(p) = &default_path {
-        append!(
-            r#"
-default_path=\""#,
-            &escape(p.to_string_lossy()),
-            r#"\"
-"#
-        );
+    out.push_str(
+        "\nif [ \"$_moho_output\" = 'file' ] ; then\n  \
+         # check if files exist\n  _moho_existing=''\n  for _moho_path in",
+    );
+    for i in 0..files.len() {
+        out.push_str(&format!(" \"$_moho_path_{i}\""));
     }
+    out.push_str(
+        r#"; do
+    if [ -f "$_moho_path" ] ; then
+      _moho_existing="${_moho_existing}  ${_moho_path}
+"
+    fi
+  done
+  if [ -n "$_moho_existing" ] ; then
+    case "$_moho_overwrite" in
+      no)
+        printf 'Error: these files already exist:\n%s' "$_moho_existing"
+        exit 1
+        ;;
+      ask)
+        printf 'These files already exist:\n%s' "$_moho_existing"
+        printf 'Overwrite them? [y/N] '
+        read -r response
+        case "$response" in
+          [yY][eE][sS]|[yY])
+            ;;
+          *)
+            echo "Stopping"
+            exit 1
+            ;;
+        esac
+        ;;
+    esac
+  fi
 
-    append!(
-        r#"
-[defaults]
+  # write the files
 "#,
     );
 
+    for i in 0..files.len() {
+        out.push_str(&format!(
+            "  mkdir -p \"$(dirname \"$_moho_path_{i}\")\"\n  \
+             printf '%s' \"$_moho_file_{i}\" > \"$_moho_path_{i}\"\n  \
+             echo \"created file at $_moho_path_{i}\"\n"
+        ));
+    }
+
+    out.push_str("else\n");
+    for i in 0..files.len() {
+        if i == 0 {
+            out.push_str("  echo \"would write to $_moho_path_0:\"\n");
+        } else {
+            out.push_str(&format!(
+                "  echo\n  echo \"would write to $_moho_path_{i}:\"\n"
+            ));
+        }
+        out.push_str(&format!(
+            "  printf '%s\\n' \"$(printf '%s' \"$_moho_file_{i}\")\"\n"
+        ));
+    }
+    out.push_str("fi\n");
+
+    out
+}
+
+/// the path as the inside of a double quoted shell string, with its variables in it
+fn path_expression(path: &OutputPath) -> String {
+    let mut out = String::new();
+    for node in &path.nodes {
+        match node {
+            Node::Text(text) => out.push_str(&escape(text)),
+            Node::Variable(v) => out.push_str(&format!("${{{v}}}")),
+            Node::Filtered(name) => out.push_str(&format!("${{{name}}}")),
+            _ => unreachable!("paths only have variables and filters"),
+        }
+    }
+    out
+}
+
+/// the path as shown in the help, with its variables in uppercase, like `src/NAME.rs`
+fn path_help(t: &Template, path: &OutputPath) -> String {
+    let mut out = String::new();
+    for node in &path.nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Variable(v) => out.push_str(&v.to_uppercase()),
+            Node::Filtered(name) => {
+                let v = t.filtered.iter().find(|f| f.name == *name);
+                out.push_str(&v.map_or(name.as_str(), |v| v.variable).to_uppercase());
+            }
+            _ => unreachable!("paths only have variables and filters"),
+        }
+    }
+    out
+}
+
+/// the options every script has, after the ones for the template's variables
+const STANDARD_OPTIONS: [(&str, &str); 5] = [
+    (
+        "--force",
+        "write files even when piped, overwriting them without asking",
+    ),
+    (
+        "--no-overwrite",
+        "write files even when piped, failing if they exist",
+    ),
+    (
+        "--dry-run",
+        "show where the output would go, without writing anything",
+    ),
+    ("--stdout", "print the output instead of writing it"),
+    (
+        "--interactive",
+        "ask for every value, with the defaults filled in",
+    ),
+];
+
+/// the lines shown by `-h`
+pub fn help(template_name: &str, t: &Template) -> Vec<String> {
+    // count how many spaces we need
+    let help_len = "-h, --help".len();
+    let name_len = "--name NAME".len();
+    let max = t
+        .variables
+        .iter()
+        .map(|v| usage(v).len())
+        .max()
+        .unwrap_or_default()
+        .max(help_len)
+        .max(name_len)
+        .max("--no-overwrite".len())
+        + 5;
+
+    let spaces = (0..max).map(|_| ' ').collect::<String>();
+
+    let description = match t.front_matter.as_ref().and_then(|f| f.description.as_ref()) {
+        Some(d) => format!(" {d}"),
+        None => String::new(),
+    };
+
+    let mut lines = vec![format!("{template_name}:{description}")];
+    if t.is_generator() {
+        lines.push("generates files at:".to_string());
+        for file in &t.files {
+            lines.push(format!("  {}", path_help(t, &file.path)));
+        }
+    } else {
+        let action = match t.front_matter.as_ref().and_then(|f| f.inject.as_ref()) {
+            Some(_) => "injects into",
+            None => "generates file at",
+        };
+        lines.push(format!("{action} {}", path_help(t, &t.path)));
+    }
+    lines.extend([
+        String::new(),
+        "options:".to_string(),
+        format!("-h, --help{}show brief help", &spaces[help_len..]),
+        format!(
+            "--name NAME{}filename (without extension)",
+            &spaces[name_len..]
+        ),
+    ]);
+
+    for v in &t.variables {
+        if v.variable == "name" {
+            continue;
+        }
+
+        let mut line = usage(v);
+        if let Some(desc) = &v.description {
+            line.push_str(&spaces[line.len()..]);
+            line.push_str(desc);
+        }
+        lines.push(line);
+    }
+    for (option, desc) in STANDARD_OPTIONS {
+        lines.push(format!("{option}{}{desc}", &spaces[option.len()..]));
+    }
+
+    lines
+}
+
+/// the toml printed by `get-vars`
+fn generate_vars(t: &Template) -> String {
+    let mut vars = String::new();
+
+    if let Some(p) = t.path.source {
+        vars.push_str(&format!("default_path={}\n", toml_string(p)));
+    }
+
+    if !t.delimiters.is_default() {
+        let delimiters =
+            toml::Value::from(vec![t.delimiters.start.as_str(), t.delimiters.end.as_str()]);
+        vars.push_str(&format!("delimiters={delimiters}\n"));
+    }
+
+    vars.push_str("\n[defaults]\n");
     for v in &t.variables {
         if let Some(default) = &v.default {
-            append!(v.variable, "=\\\"", default, "\\\"\n");
+            vars.push_str(&format!("{}={}\n", v.variable, toml_string(default)));
         }
     }
 
-    append!(
-        r#"
-[descriptions]
-"#,
-    );
+    vars.push_str("\n[types]\n");
+    for v in &t.variables {
+        if v.kind != VariableType::String {
+            vars.push_str(&format!(
+                "{}={}\n",
+                v.variable,
+                toml_string(v.kind.to_string())
+            ));
+        }
+    }
 
+    vars.push_str("\n[descriptions]\n");
     for v in &t.variables {
         if let Some(desc) = &v.description {
-            append!(v.variable, "=\\\"", desc, "\\\"\n");
+            vars.push_str(&format!("{}={}\n", v.variable, toml_string(desc)));
         }
     }
 
-    append!(
-        r#"
-"
-fi
-"#
-    );
+    vars
+}
+
+fn toml_string(s: impl AsRef<str>) -> String {
+    toml::Value::from(s.as_ref()).to_string()
+}
 
-    script
+/// how the option is shown in the help, like `--var VAR` or `--[no-]flag`
+fn usage(v: &Variable) -> String {
+    match &v.kind {
+        VariableType::String => format!("--{} {}", v.variable, v.variable.to_uppercase()),
+        VariableType::Bool => format!("--[no-]{}", v.variable),
+        VariableType::Integer => format!("--{} INTEGER", v.variable),
+        VariableType::Enum(choices) => format!("--{} {}", v.variable, choices.join("|")),
+    }
+}
+
+/// generates the code that builds the `_moho_out` variable from the template's nodes
+pub fn generate_output(nodes: &[Node]) -> String {
+    let mut out = String::new();
+    let mut started = false;
+    write_nodes(&mut out, nodes, 0, &mut started);
+    start(&mut out, &mut started);
+
+    out
+}
+
+fn write_nodes(out: &mut String, nodes: &[Node], depth: usize, started: &mut bool) {
+    let indent = "  ".repeat(depth);
+    let mut inline = String::new();
+
+    macro_rules! flush {
+        () => {
+            if !inline.is_empty() {
+                out.push_str(&indent);
+                if *started {
+                    out.push_str("_moho_out=\"${_moho_out}");
+                } else {
+                    out.push_str("_moho_out=\"");
+                    *started = true;
+                }
+                out.push_str(&inline);
+                out.push_str("\"\n");
+                inline.clear();
+            }
+        };
+    }
+
+    for node in nodes {
+        match node {
+            Node::Text(text) => inline.push_str(&escape(text)),
+            Node::Variable(v) => {
+                inline.push_str("${");
+                inline.push_str(v);
+                inline.push('}');
+            }
+            Node::Filtered(name) => {
+                inline.push_str("${");
+                inline.push_str(name);
+                inline.push('}');
+            }
+            Node::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                flush!();
+                start(out, started);
+
+                out.push_str(&indent);
+                out.push_str("if ");
+                out.push_str(&generate_condition(condition));
+                out.push_str("; then\n");
+                write_branch(out, then, depth + 1, started);
+                if !otherwise.is_empty() {
+                    out.push_str(&indent);
+                    out.push_str("else\n");
+                    write_branch(out, otherwise, depth + 1, started);
+                }
+                out.push_str(&indent);
+                out.push_str("fi\n");
+            }
+            Node::For {
+                variable,
+                list,
+                body,
+                filtered,
+            } => {
+                flush!();
+                start(out, started);
+
+                // split the list on commas, without expanding globs
+                out.push_str(&indent);
+                out.push_str("IFS=','; set -f\n");
+                out.push_str(&indent);
+                out.push_str(&format!("for {variable} in ${list}; do\n"));
+                out.push_str(&indent);
+                out.push_str("  unset IFS; set +f\n");
+                for v in filtered {
+                    out.push_str(&indent);
+                    out.push_str(&format!("  {}={}\n", v.name, filtered_expression(v)));
+                }
+                write_nodes(out, body, depth + 1, started);
+                out.push_str(&indent);
+                out.push_str("done\n");
+                out.push_str(&indent);
+                out.push_str("unset IFS; set +f\n");
+            }
+        }
+    }
+
+    flush!();
+}
+
+/// blocks append to `_moho_out`, so it has to be declared before the first one
+fn start(out: &mut String, started: &mut bool) {
+    if !*started {
+        out.push_str("_moho_out=\"\"\n");
+        *started = true;
+    }
+}
+
+fn write_branch(out: &mut String, nodes: &[Node], depth: usize, started: &mut bool) {
+    let len = out.len();
+    write_nodes(out, nodes, depth, started);
+
+    // shells don't allow empty branches
+    if out.len() == len {
+        out.push_str(&"  ".repeat(depth));
+        out.push_str(":\n");
+    }
+}
+
+fn generate_condition(condition: &Condition) -> String {
+    match condition {
+        Condition::NotEmpty(v) => format!("[ -n \"${v}\" ]"),
+        Condition::Equals(v, value) => format!("[ \"${v}\" = \"{}\" ]", escape(value)),
+        Condition::NotEquals(v, value) => format!("[ \"${v}\" != \"{}\" ]", escape(value)),
+    }
+}
+
+/// generates the nested filter calls for a filtered variable, like `$(lower "$(upper "$var")")`
+fn filtered_expression(v: &FilteredVariable) -> String {
+    let mut s = format!("\"${}\"", v.variable);
+    for filter in &v.filters {
+        let mut call = format!("\"$({} {s}", filter.name);
+        for argument in &filter.arguments {
+            call.push(' ');
+            match argument {
+                FilterArgument::String(a) => call.push_str(&quote(a)),
+                FilterArgument::Integer(i) => call.push_str(&i.to_string()),
+            }
+        }
+        call.push_str(")\"");
+        s = call;
+    }
+
+    // the outermost call doesn't need to be quoted, since it's only used in assignments
+    if v.filters.is_empty() {
+        s
+    } else {
+        s[1..s.len() - 1].to_string()
+    }
 }
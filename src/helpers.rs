@@ -1,17 +1,68 @@
-use color_eyre::eyre::Result;
-use std::{fs, os::unix::prelude::PermissionsExt};
+use color_eyre::eyre::{eyre, Result};
+use std::{
+    fs,
+    io::{self, Write},
+};
 
+/// lets the script run on its own, other systems don't have the executable bit
+#[cfg(unix)]
 pub fn make_executable(path: &str) -> Result<()> {
+    use std::os::unix::prelude::PermissionsExt;
+
     let mut perms = fs::metadata(path)?.permissions();
     perms.set_mode(0o755);
     fs::set_permissions(path, perms)?;
     Ok(())
 }
 
+#[cfg(not(unix))]
+pub fn make_executable(_path: &str) -> Result<()> {
+    Ok(())
+}
+
+/// saves the script for a template at `.moho/NAME.mh`
+pub fn write_template(name: &str, script: &str) -> Result<()> {
+    let path = format!(".moho/{name}.mh");
+    fs::create_dir_all(".moho")?;
+    fs::write(&path, script)?;
+    make_executable(&path)
+}
+
 pub fn escape(s: impl AsRef<str>) -> String {
     s.as_ref()
+        .replace('\\', "\\\\")
         .replace('"', "\\\"")
         .replace('$', "\\$")
         .replace('`', "\\`")
-        .replace('\\', "\\\\")
+}
+
+/// wraps a string in single quotes, so the shell doesn't interpret it at all
+pub fn quote(s: impl AsRef<str>) -> String {
+    format!("'{}'", s.as_ref().replace('\'', r"'\''"))
+}
+
+/// opens the editor with `initial`, reopening it with the same text for as long as
+/// `validate` fails and the user wants to fix it
+pub fn edit_until_valid(initial: &str, validate: impl Fn(&str) -> Result<()>) -> Result<String> {
+    let mut text = edit::edit(initial)?;
+
+    loop {
+        let Err(e) = validate(&text) else {
+            return Ok(text);
+        };
+
+        eprintln!("Error: {e:?}");
+        print!("reopen the editor to fix it? [Y/n] ");
+        io::stdout().flush()?;
+
+        let mut answer = String::new();
+        if io::stdin().read_line(&mut answer)? == 0
+            || matches!(answer.trim(), "n" | "N" | "no" | "No")
+        {
+            // the error was already shown above
+            return Err(eyre!("the template was not saved"));
+        }
+
+        text = edit::edit(&text)?;
+    }
 }
@@ -9,7 +9,18 @@ pub fn init() -> Result<()> {
     if !filters.is_file() {
         std::fs::write(
             filters,
-            "#!/bin/sh\nset -e\n\n# write your custom filters here\n",
+            r#"#!/bin/sh
+set -e
+
+# write your custom filters here
+#
+# filters are functions that take the value as their first argument, and print the result.
+# the ones used by a template get copied into its script, eg:
+#
+# shout() {
+#   printf '%s!\n' "$1"
+# }
+"#,
         )?;
     }
 
@@ -3,23 +3,30 @@ use std::ffi::OsStr;
 use super::*;
 
 pub fn list_templates() -> Result<()> {
-    let res = std::fs::read_dir(".moho")?
-        .into_iter()
+    for name in template_names()? {
+        println!("{name}");
+        // TODO show template descriptions
+
+        let script = std::fs::read_to_string(format!(".moho/{name}.mh"))?;
+        if let Some(warning) = Status::of(&script).warning(&name) {
+            eprintln!("warning: {warning}");
+        }
+    }
+
+    Ok(())
+}
+
+/// names of the templates in `.moho`, sorted
+pub fn template_names() -> Result<Vec<String>> {
+    let mut names = std::fs::read_dir(".moho")?
         .flatten()
         .map(|e| e.path())
         .filter(|p| {
             p.is_file() && p.file_stem().is_some() && p.extension() == Some(OsStr::new("mh"))
         })
+        .map(|p| p.file_stem().unwrap().to_string_lossy().to_string())
         .collect::<Vec<_>>();
+    names.sort();
 
-    if res.is_empty() {
-        return Ok(());
-    }
-
-    for t in res {
-        println!("{}", t.file_stem().unwrap().to_string_lossy());
-        // TODO show template descriptions
-    }
-
-    Ok(())
+    Ok(names)
 }
@@ -1,15 +1,18 @@
 use clap::Parser;
 use color_eyre::eyre::{eyre, Result};
 use std::{
-    fs::File,
     io::{self, Write},
     path::PathBuf,
 };
 
+mod ere;
 mod filters;
+mod front_matter;
 mod generate;
 mod helpers;
 mod parse;
+mod partials;
+mod script;
 #[cfg(test)]
 mod tests;
 
@@ -18,9 +21,14 @@ mod delete_template;
 mod edit_template;
 mod init;
 mod list_templates;
+mod run_template;
+mod upgrade_templates;
 
+use front_matter::*;
 use generate::*;
 use parse::*;
+use partials::*;
+use script::*;
 
 /// code generation templating toolkit
 #[derive(Parser, Debug)]
@@ -33,29 +41,7 @@ struct Args {
 #[derive(clap::Subcommand, Debug)]
 enum Action {
     /// Create a new template
-    Create {
-        /// name for the template
-        ///
-        /// will create a template script at `.moho/NAME.mh`, which you can run by calling
-        /// the file directly, or by `bash .moho/NAME.mh`
-        #[clap(value_parser)]
-        name: String,
-        /// default path the template will output to
-        ///
-        /// should end in `name.ext`
-        ///
-        /// eg: if it's set to `/path/to/name.rs`, and the template is called with
-        /// `--name hi`, the file will be created at `/path/to/hi.rs`
-        ///
-        /// if none is provided, the file will be created at `name` in the current directory
-        #[clap(name = "path", short, long, value_parser)]
-        default_path: Option<PathBuf>,
-        /// if set, the editor will be prefilled with this file's contents
-        ///
-        /// useful for creating templates out of existing files
-        #[clap(short, long, value_parser)]
-        source: Option<PathBuf>,
-    },
+    Create(CreateArgs),
     /// Edit an existing template
     Edit {
         /// name for the template to edit
@@ -74,26 +60,116 @@ enum Action {
     },
     /// List all existing templates in current directory
     List,
+    /// Render a template without a shell, the same way its script does
+    ///
+    /// eg: `moho run controller --model User --name UserController`
+    #[clap(setting = clap::AppSettings::TrailingVarArg | clap::AppSettings::AllowHyphenValues)]
+    Run {
+        /// name for the template to run
+        ///
+        /// file at `.moho/NAME.mh` must exist
+        #[clap(value_parser)]
+        name: String,
+        /// arguments for the template, like `--model User`. `-h` shows its options
+        #[clap(value_parser, multiple_values = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Regenerate all templates with this version of moho
+    ///
+    /// the templates are generated again from the source and metadata saved in them
+    Upgrade {
+        /// don't change anything, only report the templates that would change
+        ///
+        /// fails if any would, useful for CI
+        #[clap(long)]
+        check: bool,
+    },
     /// Creates an empty .moho directory
     ///
     /// this is not strictly necessary, the `create` command will create the directory if it does not exist
     Init,
 }
 
+#[derive(clap::Args, Debug)]
+pub struct CreateArgs {
+    /// name for the template
+    ///
+    /// will create a template script at `.moho/NAME.mh`, which you can run by calling
+    /// the file directly, or by `bash .moho/NAME.mh`
+    #[clap(value_parser)]
+    name: String,
+    /// default path the template will output to
+    ///
+    /// should end in `name.ext`
+    ///
+    /// eg: if it's set to `/path/to/name.rs`, and the template is called with
+    /// `--name hi`, the file will be created at `/path/to/hi.rs`
+    ///
+    /// it can also use variables and filters like the template, eg:
+    /// `app/{{ module }}/{{ name | pascal }}.php`
+    ///
+    /// if none is provided, the file will be created at `name` in the current directory
+    #[clap(name = "path", short, long, value_parser)]
+    default_path: Option<PathBuf>,
+    /// if set, the editor will be prefilled with this file's contents
+    ///
+    /// useful for creating templates out of existing files
+    #[clap(short, long, value_parser)]
+    source: Option<PathBuf>,
+    /// strings that start and end tags in the template, separated by a space
+    ///
+    /// useful for templates of files that already use `{{ }}`, eg: `--delimiters "<% %>"`
+    #[clap(short, long, value_parser)]
+    delimiters: Option<Delimiters>,
+    /// read the template from this file, instead of opening the editor
+    #[clap(long, value_parser, conflicts_with_all = &["source", "from-stdin"])]
+    template_file: Option<PathBuf>,
+    /// read the template from stdin, instead of opening the editor
+    ///
+    /// implies `--no-prompt`, since stdin is already used
+    #[clap(long, conflicts_with = "source")]
+    from_stdin: bool,
+    /// default value for a variable, like `--default namespace=App`
+    ///
+    /// can be used multiple times
+    #[clap(long = "default", value_name = "VAR=VALUE", value_parser = parse_assignment)]
+    defaults: Vec<(String, String)>,
+    /// description for a variable, like `--description namespace="controller namespace"`
+    ///
+    /// can be used multiple times
+    #[clap(long = "description", value_name = "VAR=TEXT", value_parser = parse_assignment)]
+    descriptions: Vec<(String, String)>,
+    /// toml file with the default path, and the types, defaults and descriptions of the
+    /// variables, in the same format printed by `.moho/NAME.mh get-vars`
+    #[clap(long, value_parser)]
+    vars_file: Option<PathBuf>,
+    /// don't ask anything, fail instead if the template is missing or invalid
+    ///
+    /// the template has to be given with `--template-file` or `--from-stdin`
+    #[clap(long)]
+    no_prompt: bool,
+}
+
+/// parses `VAR=VALUE`
+fn parse_assignment(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((var, value)) if !var.is_empty() => Ok((var.to_string(), value.to_string())),
+        _ => Err(format!("expected `VAR=VALUE`, found `{s}`")),
+    }
+}
+
 fn main() -> Result<()> {
     color_eyre::install()?;
 
     let args = Args::parse();
 
     match args.action {
-        Action::Create {
-            name,
-            default_path,
-            source,
-        } => create_template::create_template(name, default_path, source),
+        Action::Create(args) => create_template::create_template(args),
         Action::Edit { name } => edit_template::edit_template(name),
         Action::Delete { name } => delete_template::delete_template(name),
         Action::List => list_templates::list_templates(),
+        Action::Run { name, args } => run_template::run_template(name, args),
+        Action::Upgrade { check } => upgrade_templates::upgrade_templates(check),
         Action::Init => init::init(),
     }
 }
@@ -101,10 +177,106 @@ fn main() -> Result<()> {
 #[derive(Debug)]
 pub struct Template<'a> {
     original: &'a str,
-    generated: String,
+    delimiters: Delimiters,
+    nodes: Vec<Node<'a>>,
     variables: Vec<Variable<'a>>,
     is_name_used: bool,
     filtered: Vec<FilteredVariable<'a>>,
+    /// metadata from the top of the template, its variables are already applied to `variables`
+    front_matter: Option<FrontMatter>,
+    /// where the output is written, set with `parse_path`
+    path: OutputPath<'a>,
+    /// the `{{ file "path" }}` blocks of a generator, which writes several files instead of
+    /// `nodes`
+    files: Vec<OutputFile<'a>>,
+}
+
+impl<'a> Template<'a> {
+    /// names of all the filters used in the template, without duplicates
+    pub fn used_filters(&self) -> Vec<&'a str> {
+        let mut filters = Vec::new();
+        for call in self.filter_calls() {
+            if !filters.contains(&call.name) {
+                filters.push(call.name);
+            }
+        }
+        filters
+    }
+
+    /// every filter call in the template, including the ones inside loops
+    pub fn filter_calls(&self) -> Vec<&FilterCall<'a>> {
+        fn collect<'a, 'b>(nodes: &'b [Node<'a>], calls: &mut Vec<&'b FilterCall<'a>>) {
+            for node in nodes {
+                match node {
+                    Node::If {
+                        then, otherwise, ..
+                    } => {
+                        collect(then, calls);
+                        collect(otherwise, calls);
+                    }
+                    Node::For { body, filtered, .. } => {
+                        calls.extend(filtered.iter().flat_map(|f| f.filters.iter()));
+                        collect(body, calls);
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut calls = self
+            .filtered
+            .iter()
+            .flat_map(|f| f.filters.iter())
+            .collect::<Vec<_>>();
+        collect(&self.nodes, &mut calls);
+        for file in &self.files {
+            collect(&file.nodes, &mut calls);
+        }
+        calls
+    }
+
+    /// generators write each of their files, instead of a single one
+    pub fn is_generator(&self) -> bool {
+        !self.files.is_empty()
+    }
+
+    /// whether `name` is needed to know where the output is written
+    pub fn path_uses_name(&self) -> bool {
+        let uses_name = |path: &OutputPath| {
+            path.nodes.iter().any(|n| match n {
+                Node::Variable(v) => *v == "name",
+                Node::Filtered(f) => self
+                    .filtered
+                    .iter()
+                    .any(|v| v.name == *f && v.variable == "name"),
+                _ => false,
+            })
+        };
+
+        if self.is_generator() {
+            self.files.iter().any(|f| uses_name(&f.path))
+        } else {
+            uses_name(&self.path)
+        }
+    }
+}
+
+/// where a template is written to, which can use variables and filters like the template
+///
+/// paths without any tags keep working like before, their file name is replaced by `name`
+#[derive(Debug, PartialEq)]
+pub struct OutputPath<'a> {
+    /// the path as it was written, `None` when there's no default path
+    source: Option<&'a str>,
+    /// only text, variables and filtered variables
+    nodes: Vec<Node<'a>>,
+}
+
+/// `{{ file "path" }} ... {{ end }}`, a file written by a generator
+#[derive(Debug, PartialEq)]
+pub struct OutputFile<'a> {
+    path: OutputPath<'a>,
+    nodes: Vec<Node<'a>>,
 }
 
 #[derive(Debug, PartialEq)]
@@ -112,6 +284,9 @@ pub struct Variable<'a> {
     variable: &'a str,
     default: Option<String>,
     description: Option<String>,
+    /// optional variables are allowed to be empty when running the template
+    optional: bool,
+    kind: VariableType,
 }
 
 impl<'a> Variable<'a> {
@@ -120,6 +295,85 @@ impl<'a> Variable<'a> {
             variable,
             default: None,
             description: None,
+            optional: false,
+            kind: VariableType::String,
+        }
+    }
+}
+
+/// the values a variable accepts when running the template
+///
+/// written as `string`, `bool`, `integer`, or the allowed values separated by `|`
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum VariableType {
+    #[default]
+    String,
+    /// set with `--var`, and unset with `--no-var`. true is `true`, and false is empty
+    Bool,
+    Integer,
+    /// one of the given values
+    Enum(Vec<String>),
+}
+
+impl VariableType {
+    /// checks that a value (like a default) can be given to a variable of this type
+    pub fn check(&self, value: &str) -> Result<()> {
+        match self {
+            VariableType::String => Ok(()),
+            VariableType::Bool => match value {
+                "true" | "false" => Ok(()),
+                _ => Err(eyre!("expected `true` or `false`, got `{value}`")),
+            },
+            VariableType::Integer => match value.parse::<i64>() {
+                Ok(_) => Ok(()),
+                Err(_) => Err(eyre!("expected an integer, got `{value}`")),
+            },
+            VariableType::Enum(choices) => {
+                if choices.iter().any(|c| c == value) {
+                    Ok(())
+                } else {
+                    Err(eyre!(
+                        "expected one of {}, got `{value}`",
+                        choices.join(", ")
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for VariableType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "" | "string" => Ok(VariableType::String),
+            "bool" | "boolean" => Ok(VariableType::Bool),
+            "int" | "integer" => Ok(VariableType::Integer),
+            s if s.contains('|') => {
+                let choices = s
+                    .split('|')
+                    .map(|c| c.trim().to_string())
+                    .collect::<Vec<_>>();
+                if choices.iter().any(|c| c.is_empty() || c.contains(' ')) {
+                    return Err(format!("choices in `{s}` can't be empty or contain spaces"));
+                }
+                Ok(VariableType::Enum(choices))
+            }
+            s => Err(format!(
+                "unknown type `{s}`, expected string, bool, integer, or choices like `model|controller`"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for VariableType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VariableType::String => write!(f, "string"),
+            VariableType::Bool => write!(f, "bool"),
+            VariableType::Integer => write!(f, "integer"),
+            VariableType::Enum(choices) => write!(f, "{}", choices.join("|")),
         }
     }
 }
@@ -127,7 +381,54 @@ impl<'a> Variable<'a> {
 #[derive(Debug, PartialEq)]
 pub struct FilteredVariable<'a> {
     variable: &'a str,
-    filters: Vec<&'a str>,
+    filters: Vec<FilterCall<'a>>,
     /// name for the filtered variable
     name: String,
 }
+
+/// a filter applied to a variable, like `replace("_", "-")`
+#[derive(Debug, PartialEq)]
+pub struct FilterCall<'a> {
+    name: &'a str,
+    arguments: Vec<FilterArgument>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FilterArgument {
+    String(String),
+    Integer(i64),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Node<'a> {
+    /// text that gets copied to the output as is
+    Text(&'a str),
+    /// `{{ variable }}`
+    Variable(&'a str),
+    /// `{{ variable | filter }}`, contains the name of the filtered variable
+    Filtered(String),
+    /// `{{ if condition }} ... {{ else }} ... {{ end }}`
+    If {
+        condition: Condition<'a>,
+        then: Vec<Node<'a>>,
+        otherwise: Vec<Node<'a>>,
+    },
+    /// `{{ for variable in list }} ... {{ end }}`, where `list` is a comma separated variable
+    For {
+        variable: &'a str,
+        list: &'a str,
+        body: Vec<Node<'a>>,
+        /// filtered versions of the loop variable, computed on every iteration
+        filtered: Vec<FilteredVariable<'a>>,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Condition<'a> {
+    /// `{{ if variable }}`
+    NotEmpty(&'a str),
+    /// `{{ if variable == "value" }}`
+    Equals(&'a str, String),
+    /// `{{ if variable != "value" }}`
+    NotEquals(&'a str, String),
+}
@@ -1,119 +1,1135 @@
 use super::*;
+use std::{fmt, path::Path, str::FromStr};
 
-pub fn parse_template(template: &str) -> Result<Template> {
-    let mut generated = String::with_capacity(template.len());
-    let mut variables: Vec<Variable> = Vec::new();
-    let mut filtered: Vec<FilteredVariable> = Vec::new();
+pub fn parse_template<'a>(
+    template: &'a str,
+    delimiters: &Delimiters,
+    partials: &'a Partials,
+) -> Result<Template<'a>> {
+    let front_matter = FrontMatter::parse(template).map_err(ParseError::into_report)?;
+    let tokens = tokenize(template, delimiters).map_err(ParseError::into_report)?;
 
-    let chars = template.chars();
-    let mut last_char = None;
+    let mut parser = Parser::new(template, delimiters, partials, tokens);
 
-    let mut var: Option<usize> = None;
+    let (nodes, terminator) = parser.parse_block().map_err(ParseError::into_report)?;
+    if let Some((tag, offset)) = terminator {
+        return Err(ParseError::new(
+            template,
+            offset,
+            format!("found `{}` without a matching block", delimiters.tag(tag)),
+        )
+        .into_report());
+    }
 
-    for (i, c) in chars.enumerate() {
-        // starting variable
-        if last_char == Some('{') && c == '{' {
-            if var.is_none() {
-                last_char = None;
-                var = Some(i + 1);
-                continue;
-            } else {
-                return Err(eyre!("nested variables are not allowed"));
+    let Parser {
+        mut variables,
+        filtered,
+        loop_variables,
+        files,
+        ..
+    } = parser;
+
+    // generators only write the contents of their file blocks
+    if let Some((_, source, offset)) = files.first() {
+        let outside = nodes.iter().any(|n| match n {
+            Node::Text(text) => !text.trim().is_empty(),
+            _ => true,
+        });
+        if outside {
+            return Err(ParseError::new(
+                source,
+                *offset,
+                format!(
+                    "templates with `{}` blocks can't have anything outside of them",
+                    delimiters.tag("file")
+                ),
+            )
+            .into_report());
+        }
+        if front_matter.as_ref().is_some_and(|f| f.path.is_some()) {
+            return Err(eyre!(
+                "templates with `{}` blocks can't have a path in their front matter, \
+                 since each file has its own",
+                delimiters.tag("file")
+            ));
+        }
+        if front_matter.as_ref().is_some_and(|f| f.inject.is_some()) {
+            return Err(eyre!(
+                "templates with `{}` blocks can't inject into a file",
+                delimiters.tag("file")
+            ));
+        }
+    }
+    let files = files.into_iter().map(|(file, _, _)| file).collect();
+
+    if let Some((v, source, offset)) = loop_variables
+        .iter()
+        .find(|(l, _, _)| variables.iter().any(|v| &v.variable == l))
+    {
+        return Err(ParseError::new(
+            source,
+            *offset,
+            format!("loop variable {v} has the same name as another variable"),
+        )
+        .into_report());
+    }
+
+    let mut is_name_used = true;
+    // insert `name` variable if not exists
+    if !variables.iter().any(|v| v.variable == "name") {
+        is_name_used = false;
+        variables.push(Variable::new("name"));
+    }
+
+    if let Some(front_matter) = &front_matter {
+        // variables only used in the path are added when it's parsed, with `parse_path`
+        let path_variables = match &front_matter.path {
+            Some(path) => {
+                let path = path.to_string_lossy();
+                let mut parser = Parser::new(&path, delimiters, &NO_PARTIALS, Vec::new());
+                parser
+                    .parse_output_path(&path, false)
+                    .map_err(ParseError::into_report)?;
+                parser
+                    .variables
+                    .iter()
+                    .map(|v| v.variable.to_string())
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+        apply_front_matter(front_matter, &mut variables, |name| {
+            path_variables.iter().any(|v| v == name)
+        })?;
+    }
+
+    Ok(Template {
+        original: template,
+        delimiters: delimiters.clone(),
+        nodes,
+        variables,
+        filtered,
+        is_name_used,
+        front_matter,
+        path: OutputPath::with_name(None),
+        files,
+    })
+}
+
+/// parses the default path of a template, its variables become options of the template
+pub fn parse_path<'a>(t: &mut Template<'a>, path: Option<&'a str>) -> Result<()> {
+    // the file to inject into already exists, so its name is used as it is
+    let inject = t.front_matter.as_ref().is_some_and(|f| f.inject.is_some());
+    let Some(path) = path else {
+        if inject {
+            return Err(eyre!(
+                "templates that inject need the path of the file they add to"
+            ));
+        }
+        return Ok(());
+    };
+
+    let mut parser = Parser::new(path, &t.delimiters, &NO_PARTIALS, Vec::new());
+    parser.variables = std::mem::take(&mut t.variables);
+    parser.filtered = std::mem::take(&mut t.filtered);
+    let parsed = parser.parse_output_path(path, inject);
+
+    let existing = t.variables.len();
+    t.variables = parser.variables;
+    t.filtered = parser.filtered;
+    t.path = parsed.map_err(|e| {
+        let message = format!("{} in the default path", e.message);
+        ParseError { message, ..e }.into_report()
+    })?;
+
+    // the front matter can describe the variables that are only in the path
+    if let Some(front_matter) = &t.front_matter {
+        apply_front_matter(front_matter, &mut t.variables[existing..], |_| true)?;
+    }
+
+    Ok(())
+}
+
+/// names of the partials included by the template, without following them
+pub fn included_names(template: &str, delimiters: &Delimiters) -> Result<Vec<String>, ParseError> {
+    let tokens = tokenize(template, delimiters)?;
+
+    let mut names = Vec::new();
+    for token in tokens {
+        if let Token::Tag { content, offset } = token {
+            if let ("include", name) = keyword(content) {
+                // the same error the parser gives, since it doesn't get to see it
+                let name = parse_literal(name).map_err(|e| {
+                    let e = e.wrap_err("partial names should be strings");
+                    ParseError::new(template, offset, e.to_string())
+                })?;
+                names.push(name);
             }
         }
+    }
+
+    Ok(names)
+}
+
+/// the strings that start and end tags, `{{` and `}}` by default
+#[derive(Debug, Clone, PartialEq)]
+pub struct Delimiters {
+    pub start: String,
+    pub end: String,
+}
+
+impl Delimiters {
+    pub fn new(start: impl Into<String>, end: impl Into<String>) -> Result<Self> {
+        let (start, end) = (start.into(), end.into());
+
+        if start.is_empty() || end.is_empty() {
+            return Err(eyre!("delimiters can't be empty"));
+        }
+        if start
+            .chars()
+            .chain(end.chars())
+            .any(|c| c.is_whitespace() || c == '"')
+        {
+            return Err(eyre!("delimiters can't contain whitespace or quotes"));
+        }
+
+        Ok(Self { start, end })
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// formats a tag with these delimiters, like `{{ end }}`
+    fn tag(&self, content: &str) -> String {
+        format!("{} {content} {}", self.start, self.end)
+    }
+}
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        Self {
+            start: "{{".to_string(),
+            end: "}}".to_string(),
+        }
+    }
+}
+
+/// parses delimiters separated by a space, like `<% %>`
+impl FromStr for Delimiters {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>()[..] {
+            [start, end] => Self::new(start, end).map_err(|e| e.to_string()),
+            _ => Err("delimiters should be two strings separated by a space, like `<% %>`".into()),
+        }
+    }
+}
+
+/// an error in the template's syntax, pointing to where it happened
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+    /// starts at 1
+    pub line: usize,
+    /// starts at 1
+    pub column: usize,
+    /// the line containing the error, with a caret pointing to the column
+    pub snippet: String,
+}
+
+impl ParseError {
+    pub fn new(template: &str, offset: usize, message: impl Into<String>) -> Self {
+        let line_start = template[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = template[offset..]
+            .find('\n')
+            .map(|i| offset + i)
+            .unwrap_or(template.len());
 
-        // ending variable
-        if last_char == Some('}') && c == '}' {
-            if let Some(start) = var.take() {
-                let var_str = &template[start..i - 1].trim();
+        let line = template[..offset].matches('\n').count() + 1;
+        let column = template[line_start..offset].chars().count() + 1;
 
-                generated.push_str("${");
-                if var_str.contains('|') {
-                    let f = parse_filtered_variable(var_str)?;
-                    generated.push_str(&f.name);
+        let number = line.to_string();
+        let gutter = " ".repeat(number.len());
+        let snippet = format!(
+            "{gutter} |\n{number} | {}\n{gutter} | {}^",
+            &template[line_start..line_end],
+            " ".repeat(column - 1),
+        );
 
-                    if !variables.iter().any(|v| v.variable == f.variable) {
-                        variables.push(Variable::new(f.variable));
+        Self {
+            message: message.into(),
+            line,
+            column,
+            snippet,
+        }
+    }
+
+    pub fn in_partial(mut self, name: &str) -> Self {
+        self.message = format!("{} in partial `{name}`", self.message);
+        self
+    }
+
+    /// converts the error into a report, with the snippet as a section
+    pub fn into_report(self) -> color_eyre::Report {
+        use color_eyre::{Section, SectionExt};
+
+        let snippet = self.snippet.clone();
+        color_eyre::Report::new(self).section(snippet.header("Template:"))
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (line {}, column {})",
+            self.message, self.line, self.column
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[derive(Debug, PartialEq)]
+enum Token<'a> {
+    Text(&'a str),
+    Tag {
+        /// contents of the tag, trimmed
+        content: &'a str,
+        /// where the tag starts in the template
+        offset: usize,
+    },
+}
+
+fn tokenize<'a>(template: &'a str, d: &Delimiters) -> Result<Vec<Token<'a>>, ParseError> {
+    let mut tokens = Vec::new();
+    // the front matter isn't part of the template's output
+    let mut offset = split_front_matter(template)?.map_or(0, |(_, body)| body);
+    // set by `-}}`, trims the whitespace at the start of the next text
+    let mut trim_next = false;
+
+    while let Some(start) = template[offset..].find(&d.start).map(|i| offset + i) {
+        let text = &template[offset..start];
+
+        // comments, like `{{# note #}}` or `{{-# note -#}}`, are left out of the output
+        let content_start = start + d.start.len();
+        let trim_before = template[content_start..].starts_with('-');
+        let comment_start = content_start + usize::from(trim_before);
+        if template[comment_start..].starts_with('#') {
+            push_text(&mut tokens, text, trim_next, trim_before);
+
+            let rest = &template[comment_start + 1..];
+            let (end, close) = rest
+                .match_indices('#')
+                .find_map(|(i, _)| {
+                    let after = &rest[i + 1..];
+                    let dash = usize::from(after.starts_with('-'));
+                    after[dash..]
+                        .starts_with(&d.end)
+                        .then_some((i, 1 + dash + d.end.len()))
+                })
+                .ok_or_else(|| ParseError::new(template, start, "comment was unfinished"))?;
+            // a `-` on either side of the closing `#` trims the whitespace after the comment
+            trim_next = close > 1 + d.end.len() || rest[..end].ends_with('-');
+            offset = comment_start + 1 + end + close;
+            continue;
+        }
+
+        let tag = find_tag(template, start, d)?;
+        push_text(&mut tokens, text, trim_next, tag.trim_before);
+        trim_next = tag.trim_after;
+        offset = tag.end;
+        let content = tag.content;
+
+        // everything between `{{ raw }}` and `{{ endraw }}` is copied as is
+        if content == "raw" {
+            let (raw_end, endraw) = find_endraw(template, offset, d).ok_or_else(|| {
+                ParseError::new(
+                    template,
+                    start,
+                    format!(
+                        "`{}` block is missing an `{}`",
+                        d.tag("raw"),
+                        d.tag("endraw")
+                    ),
+                )
+            })?;
+            push_text(
+                &mut tokens,
+                &template[offset..raw_end],
+                trim_next,
+                endraw.trim_before,
+            );
+            trim_next = endraw.trim_after;
+            offset = endraw.end;
+            continue;
+        }
+
+        // `{{ "{{" }}` outputs the string as is
+        if content.starts_with('"') {
+            parse_literal(content).map_err(|e| ParseError::new(template, start, e.to_string()))?;
+            tokens.extend(literal_pieces(content).into_iter().map(Token::Text));
+            continue;
+        }
+
+        tokens.push(Token::Tag {
+            content,
+            offset: start,
+        });
+    }
+
+    push_text(&mut tokens, &template[offset..], trim_next, false);
+
+    Ok(tokens)
+}
+
+fn push_text<'a>(tokens: &mut Vec<Token<'a>>, mut text: &'a str, trim_start: bool, trim_end: bool) {
+    if trim_start {
+        text = text.trim_start();
+    }
+    if trim_end {
+        text = text.trim_end();
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+}
+
+/// a tag as it's written in the template
+struct RawTag<'a> {
+    /// contents of the tag, trimmed, and without whitespace control markers
+    content: &'a str,
+    /// where the tag ends in the template
+    end: usize,
+    /// `{{-`, removes the whitespace before the tag
+    trim_before: bool,
+    /// `-}}`, removes the whitespace after the tag
+    trim_after: bool,
+}
+
+impl<'a> RawTag<'a> {
+    fn new(inner: &'a str, end: usize) -> Self {
+        let trim_before = inner.starts_with('-');
+        let trim_after = inner.len() > 1 && inner.ends_with('-');
+
+        let mut content = inner;
+        if trim_before {
+            content = &content[1..];
+        }
+        if trim_after {
+            content = &content[..content.len() - 1];
+        }
+
+        Self {
+            content: content.trim(),
+            end,
+            trim_before,
+            trim_after,
+        }
+    }
+}
+
+/// reads the tag starting at `start`
+///
+/// delimiters inside of strings don't count
+fn find_tag<'a>(template: &'a str, start: usize, d: &Delimiters) -> Result<RawTag<'a>, ParseError> {
+    let content_start = start + d.start.len();
+    let inner = &template[content_start..];
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in inner.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if in_string {
+            match c {
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if inner[i..].starts_with(&d.end) {
+            return Ok(RawTag::new(&inner[..i], content_start + i + d.end.len()));
+        } else if inner[i..].starts_with(&d.start) {
+            return Err(ParseError::new(
+                template,
+                content_start + i,
+                "nested variables are not allowed",
+            ));
+        }
+    }
+
+    Err(ParseError::new(template, start, "variable was unfinished"))
+}
+
+/// finds the next `{{ endraw }}` after `offset`, returning where it starts and the tag
+fn find_endraw<'a>(
+    template: &'a str,
+    mut offset: usize,
+    d: &Delimiters,
+) -> Option<(usize, RawTag<'a>)> {
+    while let Some(start) = template[offset..].find(&d.start).map(|i| offset + i) {
+        let content_start = start + d.start.len();
+        let inner = &template[content_start..];
+        if let Some(end) = inner.find(&d.end) {
+            let tag = RawTag::new(&inner[..end], content_start + end + d.end.len());
+            if tag.content == "endraw" {
+                return Some((start, tag));
+            }
+        }
+        offset = content_start;
+    }
+
+    None
+}
+
+struct Parser<'a, 'd> {
+    /// the source being parsed, which changes while parsing partials
+    template: &'a str,
+    delimiters: &'d Delimiters,
+    partials: &'a Partials,
+    tokens: std::vec::IntoIter<Token<'a>>,
+    /// partials we are currently inside of, innermost last
+    includes: Vec<&'a str>,
+    variables: Vec<Variable<'a>>,
+    filtered: Vec<FilteredVariable<'a>>,
+    /// loops we are currently inside of, innermost last
+    loops: Vec<Loop<'a>>,
+    /// every loop variable declared in the template, with the source and offset it was declared at
+    loop_variables: Vec<(&'a str, &'a str, usize)>,
+    /// how many blocks we are currently inside of
+    depth: usize,
+    /// the file blocks of a generator, with the source and offset they were declared at
+    files: Vec<(OutputFile<'a>, &'a str, usize)>,
+}
+
+struct Loop<'a> {
+    variable: &'a str,
+    filtered: Vec<FilteredVariable<'a>>,
+}
+
+/// a tag that ends a block, and where it is
+type Terminator<'a> = Option<(&'a str, usize)>;
+
+impl<'a, 'd> Parser<'a, 'd> {
+    fn new(
+        template: &'a str,
+        delimiters: &'d Delimiters,
+        partials: &'a Partials,
+        tokens: Vec<Token<'a>>,
+    ) -> Self {
+        Self {
+            template,
+            delimiters,
+            partials,
+            tokens: tokens.into_iter(),
+            includes: Vec::new(),
+            variables: Vec::new(),
+            filtered: Vec::new(),
+            loops: Vec::new(),
+            loop_variables: Vec::new(),
+            depth: 0,
+            files: Vec::new(),
+        }
+    }
+}
+
+impl<'a> Parser<'a, '_> {
+    /// parses nodes until the end of the template, or until an `else` or `end` tag is found
+    ///
+    /// returns the parsed nodes, and the tag that ended the block, if any
+    fn parse_block(&mut self) -> Result<(Vec<Node<'a>>, Terminator<'a>), ParseError> {
+        let mut nodes = Vec::new();
+
+        while let Some(token) = self.tokens.next() {
+            let (tag, offset) = match token {
+                Token::Text(text) => {
+                    nodes.push(Node::Text(text));
+                    continue;
+                }
+                Token::Tag { content, offset } => (content, offset),
+            };
+            let error =
+                |e: color_eyre::Report| ParseError::new(self.template, offset, e.to_string());
+
+            match keyword(tag) {
+                ("else" | "end", "") => return Ok((nodes, Some((tag, offset)))),
+                ("if", condition) => {
+                    let condition = self.parse_condition(condition).map_err(error)?;
+
+                    let (then, mut terminator) = self.parse_nested()?;
+                    let mut otherwise = Vec::new();
+                    if let Some(("else", _)) = terminator {
+                        (otherwise, terminator) = self.parse_nested()?;
                     }
+                    self.expect_end("if", offset, terminator)?;
+
+                    nodes.push(Node::If {
+                        condition,
+                        then,
+                        otherwise,
+                    });
+                }
+                ("for", header) => {
+                    let (variable, list) = self.parse_loop_header(header, offset).map_err(error)?;
+
+                    self.loops.push(Loop {
+                        variable,
+                        filtered: Vec::new(),
+                    });
+                    let (body, terminator) = self.parse_nested()?;
+                    self.expect_end("for", offset, terminator)?;
+                    let Loop { filtered, .. } = self.loops.pop().expect("loop was pushed above");
 
-                    if !filtered
+                    nodes.push(Node::For {
+                        variable,
+                        list,
+                        body,
+                        filtered,
+                    });
+                }
+                // `file` is also a common variable name, so only a string starts a block
+                ("file", path) if path.starts_with('"') => {
+                    if self.depth > 0 {
+                        return Err(ParseError::new(
+                            self.template,
+                            offset,
+                            format!(
+                                "`{}` blocks can't be inside of other blocks",
+                                self.delimiters.tag("file")
+                            ),
+                        ));
+                    }
+                    let literal = parse_literal(path)
+                        .map_err(|e| error(e.wrap_err("file paths should be strings")))?;
+                    if literal.is_empty() {
+                        return Err(error(eyre!("file paths can't be empty")));
+                    }
+                    // the path is parsed from the template, so it can't have escapes
+                    if literal.contains('\\') {
+                        return Err(error(eyre!(
+                            "file paths can't contain backslashes, use `/` instead"
+                        )));
+                    }
+                    let source = &path[1..path.len() - 1];
+                    let path = self.parse_output_path(source, false)?;
+                    // paths without tags have their file name replaced by the name, so
+                    // different sources can still end up at the same file
+                    let same = self
+                        .files
                         .iter()
-                        .any(|v| v.variable == f.variable && v.filters == f.filters)
-                    {
-                        filtered.push(f);
+                        .find(|(f, _, _)| f.path.nodes == path.nodes);
+                    match same.and_then(|(f, _, _)| f.path.source) {
+                        Some(other) if other == source => {
+                            return Err(error(eyre!("there's already a file block for `{source}`")))
+                        }
+                        Some(other) => {
+                            return Err(error(eyre!(
+                                "`{source}` is written to the same file as `{other}`, \
+                                 since file names without tags are replaced by the name"
+                            )))
+                        }
+                        None => {}
                     }
-                } else {
-                    validate_ident(var_str)?;
 
-                    generated.push_str(var_str);
-                    if !variables.iter().any(|v| &v.variable == var_str) {
-                        variables.push(Variable::new(var_str));
+                    let (nodes, terminator) = self.parse_nested()?;
+                    self.expect_end("file", offset, terminator)?;
+
+                    let file = OutputFile { path, nodes };
+                    self.files.push((file, self.template, offset));
+                }
+                ("include", name) => {
+                    let name = parse_literal(name)
+                        .map_err(|e| error(e.wrap_err("partial names should be strings")))?;
+                    let partial = self.include(&name).map_err(error)?;
+
+                    let template = std::mem::replace(&mut self.template, partial);
+                    let tokens =
+                        tokenize(partial, self.delimiters).map_err(|e| e.in_partial(&name))?;
+                    let tokens = std::mem::replace(&mut self.tokens, tokens.into_iter());
+
+                    let included = self.parse_block().map_err(|e| e.in_partial(&name));
+
+                    self.template = template;
+                    self.tokens = tokens;
+                    self.includes.pop();
+
+                    match included? {
+                        (included, None) => nodes.extend(included),
+                        (_, Some((tag, _))) => {
+                            return Err(ParseError::new(
+                                self.template,
+                                offset,
+                                format!(
+                                    "partial `{name}` has a `{}` without a matching block",
+                                    self.delimiters.tag(tag)
+                                ),
+                            ))
+                        }
                     }
                 }
-                generated.push('}');
+                _ => nodes.push(self.parse_variable(tag).map_err(error)?),
+            }
+        }
 
-                last_char = None;
-                continue;
+        Ok((nodes, None))
+    }
+
+    /// parses a path to write to, which can only have variables and filters
+    ///
+    /// paths without tags have their file name replaced by `name`, unless they're `literal`
+    fn parse_output_path(
+        &mut self,
+        source: &'a str,
+        literal: bool,
+    ) -> Result<OutputPath<'a>, ParseError> {
+        let tokens = tokenize(source, self.delimiters)?;
+        if !literal && tokens.iter().all(|t| matches!(t, Token::Text(_))) {
+            return Ok(OutputPath::with_name(Some(source)));
+        }
+
+        let template = std::mem::replace(&mut self.template, source);
+        let tokens = std::mem::replace(&mut self.tokens, tokens.into_iter());
+        let parsed = self.parse_nested();
+        self.template = template;
+        self.tokens = tokens;
+
+        let nodes = match parsed? {
+            (nodes, None) => nodes,
+            (_, Some((tag, offset))) => {
+                return Err(ParseError::new(
+                    source,
+                    offset,
+                    format!(
+                        "found `{}` without a matching block",
+                        self.delimiters.tag(tag)
+                    ),
+                ))
             }
+        };
+        if nodes
+            .iter()
+            .any(|n| matches!(n, Node::If { .. } | Node::For { .. }))
+        {
+            return Err(ParseError::new(
+                source,
+                0,
+                "paths can only have variables and filters",
+            ));
         }
 
-        if var.is_none() {
-            if let Some(l) = last_char {
-                if l == '"' || l == '$' || l == '`' || l == '\\' {
-                    generated.push('\\');
-                }
+        Ok(OutputPath {
+            source: Some(source),
+            nodes,
+        })
+    }
+
+    /// parses the body of a block, see `parse_block`
+    fn parse_nested(&mut self) -> Result<(Vec<Node<'a>>, Terminator<'a>), ParseError> {
+        self.depth += 1;
+        let block = self.parse_block();
+        self.depth -= 1;
+        block
+    }
+
+    /// fails unless the block starting at `offset` was ended by an `end` tag
+    fn expect_end(
+        &self,
+        keyword: &str,
+        offset: usize,
+        terminator: Terminator,
+    ) -> Result<(), ParseError> {
+        match terminator {
+            Some(("end", _)) => Ok(()),
+            _ => Err(ParseError::new(
+                self.template,
+                offset,
+                format!(
+                    "`{}` block is missing an `{}`",
+                    self.delimiters.tag(keyword),
+                    self.delimiters.tag("end")
+                ),
+            )),
+        }
+    }
+
+    /// gets the source for a partial, making sure it's not already being included
+    fn include(&mut self, name: &str) -> Result<&'a str> {
+        let (name, partial) = self
+            .partials
+            .sources()
+            .find(|(n, _)| *n == name)
+            .ok_or_else(|| eyre!("partial `{name}` was not found"))?;
 
-                generated.push(l);
+        if self.includes.contains(&name) {
+            let cycle = self
+                .includes
+                .iter()
+                .skip_while(|n| **n != name)
+                .chain([&name])
+                .copied()
+                .collect::<Vec<_>>();
+            return Err(eyre!("partials include each other: {}", cycle.join(" -> ")));
+        }
+
+        self.includes.push(name);
+        Ok(partial)
+    }
+
+    fn parse_variable(&mut self, tag: &'a str) -> Result<Node<'a>> {
+        // `{{ variable = "default" # description }}`
+        let (tag, description) = match split_outside_strings(tag, '#')?[..] {
+            [variable, _, ..] => {
+                let description = tag[variable.len() + 1..].trim();
+                (variable, Some(description).filter(|d| !d.is_empty()))
             }
+            _ => (tag, None),
+        };
+        let (tag, default) = match split_outside_strings(tag, '=')?[..] {
+            [tag] => (tag.trim(), None),
+            [tag, default] => (tag.trim(), Some(parse_literal(default.trim())?)),
+            _ => return Err(eyre!("`{tag}` has more than one default")),
+        };
+
+        let node = self.parse_variable_expression(tag)?;
+        if default.is_some() || description.is_some() {
+            let variable = match tag.split_once('|') {
+                Some((variable, _)) => variable.trim(),
+                None => tag,
+            };
+            self.set_metadata(variable, default, description)?;
         }
 
-        last_char = Some(c);
+        Ok(node)
     }
 
-    if var.is_some() {
-        return Err(eyre!("variable was unfinished"));
+    fn parse_variable_expression(&mut self, tag: &'a str) -> Result<Node<'a>> {
+        if tag.contains('|') {
+            let mut f = parse_filtered_variable(tag)?;
+
+            self.add_variable(f.variable);
+            // `default` takes care of empty values
+            if f.filters.iter().any(|c| c.name == "default") {
+                self.set_optional(f.variable);
+            }
+
+            // filtered loop variables have to be computed inside of their loop
+            let filtered = match self
+                .loops
+                .iter_mut()
+                .rev()
+                .find(|l| l.variable == f.variable)
+            {
+                Some(l) => &mut l.filtered,
+                None => &mut self.filtered,
+            };
+
+            if let Some(existing) = filtered
+                .iter()
+                .find(|v| v.variable == f.variable && v.filters == f.filters)
+            {
+                return Ok(Node::Filtered(existing.name.clone()));
+            }
+
+            // the same filters with different arguments need different names
+            let base = f.name.clone();
+            let mut n = 2;
+            while filtered.iter().any(|v| v.name == f.name) {
+                f.name = format!("{base}_{n}");
+                n += 1;
+            }
+
+            let node = Node::Filtered(f.name.clone());
+            filtered.push(f);
+
+            Ok(node)
+        } else {
+            validate_ident(tag)?;
+            self.add_variable(tag);
+
+            Ok(Node::Variable(tag))
+        }
     }
 
-    if let Some(l) = last_char {
-        if l == '"' || l == '$' || l == '`' || l == '\\' {
-            generated.push('\\');
+    fn parse_condition(&mut self, condition: &'a str) -> Result<Condition<'a>> {
+        if condition.is_empty() {
+            return Err(eyre!("`{}` needs a condition", self.delimiters.tag("if")));
         }
 
-        generated.push(l);
+        // the value can have `==` or `!=` in it too
+        let operator = ["==", "!="]
+            .into_iter()
+            .filter_map(|op| Some((find_outside_strings(condition, op)?, op)))
+            .min();
+        let (variable, condition) = match operator {
+            Some((i, op)) => {
+                let variable = condition[..i].trim();
+                let literal = parse_literal(condition[i + op.len()..].trim())?;
+                let condition = if op == "==" {
+                    Condition::Equals(variable, literal)
+                } else {
+                    Condition::NotEquals(variable, literal)
+                };
+                (variable, condition)
+            }
+            None => (condition, Condition::NotEmpty(condition)),
+        };
+
+        validate_ident(variable)?;
+        self.add_variable(variable);
+        // variables used in conditions are allowed to be empty
+        self.set_optional(variable);
+
+        Ok(condition)
     }
 
-    let mut is_name_used = true;
-    // insert `name` variable if not exists
-    if !variables.iter().any(|v| v.variable == "name") {
-        is_name_used = false;
-        variables.push(Variable {
-            variable: "name",
-            default: None,
-            description: None,
-        });
+    /// parses `field in fields`, returning the loop variable and the list variable
+    fn parse_loop_header(&mut self, header: &'a str, offset: usize) -> Result<(&'a str, &'a str)> {
+        let (variable, list) = match header.split_whitespace().collect::<Vec<_>>()[..] {
+            [variable, "in", list] => (variable, list),
+            _ => {
+                return Err(eyre!(
+                    "`{}` should look like `{}`",
+                    self.delimiters.tag(&format!("for {header}")),
+                    self.delimiters.tag("for item in list")
+                ))
+            }
+        };
+
+        validate_ident(variable)?;
+        validate_ident(list)?;
+        if self.is_loop_variable(variable) {
+            return Err(eyre!("loop variable {variable} is already in use"));
+        }
+
+        self.add_variable(list);
+        self.loop_variables.push((variable, self.template, offset));
+
+        Ok((variable, list))
     }
 
-    Ok(Template {
-        original: template,
-        generated,
-        variables,
-        filtered,
-        is_name_used,
-    })
+    /// adds the variable to the template's variables, unless it's a loop variable
+    fn add_variable(&mut self, variable: &'a str) {
+        if !self.is_loop_variable(variable)
+            && !self.variables.iter().any(|v| v.variable == variable)
+        {
+            self.variables.push(Variable::new(variable));
+        }
+    }
+
+    /// sets the default and description written next to a variable
+    fn set_metadata(
+        &mut self,
+        variable: &str,
+        default: Option<String>,
+        description: Option<&str>,
+    ) -> Result<()> {
+        if self.is_loop_variable(variable) {
+            return Err(eyre!(
+                "loop variable {variable} can't have a default or a description"
+            ));
+        }
+
+        let v = self
+            .variables
+            .iter_mut()
+            .find(|v| v.variable == variable)
+            .expect("variable was added when parsing it");
+        merge_metadata(v, default, description)
+    }
+
+    fn set_optional(&mut self, variable: &str) {
+        if let Some(v) = self.variables.iter_mut().find(|v| v.variable == variable) {
+            v.optional = true;
+        }
+    }
+
+    fn is_loop_variable(&self, variable: &str) -> bool {
+        self.loops.iter().any(|l| l.variable == variable)
+    }
+}
+
+/// sets the default and description of the variable, unless it already has different ones
+fn merge_metadata(
+    v: &mut Variable,
+    default: Option<String>,
+    description: Option<&str>,
+) -> Result<()> {
+    if let Some(default) = default {
+        match &v.default {
+            Some(d) if *d != default => {
+                return Err(eyre!(
+                    "variable {} has two different defaults, `{d}` and `{default}`",
+                    v.variable
+                ))
+            }
+            _ => v.default = Some(default),
+        }
+    }
+    if let Some(description) = description {
+        match &v.description {
+            Some(d) if d != description => {
+                return Err(eyre!(
+                    "variable {} has two different descriptions, `{d}` and `{description}`",
+                    v.variable
+                ))
+            }
+            _ => v.description = Some(description.to_string()),
+        }
+    }
+
+    Ok(())
+}
+
+/// applies the types, defaults and descriptions from the front matter to the variables
+///
+/// the ones missing from `variables` are an error, unless `elsewhere` knows about them
+fn apply_front_matter(
+    front_matter: &FrontMatter,
+    variables: &mut [Variable],
+    elsewhere: impl Fn(&str) -> bool,
+) -> Result<()> {
+    for (name, metadata) in &front_matter.variables {
+        let Some(v) = variables.iter_mut().find(|v| v.variable == name) else {
+            if elsewhere(name) {
+                continue;
+            }
+            return Err(eyre!(
+                "variable {name} is in the front matter, but not in the template"
+            ));
+        };
+
+        if let Some(kind) = &metadata.kind {
+            v.kind = kind.clone();
+        }
+        merge_metadata(v, metadata.default.clone(), metadata.description.as_deref())?;
+
+        if let Some(default) = &v.default {
+            v.kind
+                .check(default)
+                .map_err(|e| e.wrap_err(format!("default for {name} doesn't fit its type")))?;
+            // false is the same as not being set
+            if v.kind == VariableType::Bool && default == "false" {
+                v.default = None;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl<'a> OutputPath<'a> {
+    /// a path without tags, its file name is replaced by `name`, keeping the extension
+    ///
+    /// without a path, the file is written to `./name`
+    fn with_name(source: Option<&'a str>) -> Self {
+        let Some(path) = source else {
+            return Self {
+                source,
+                nodes: vec![Node::Text("./"), Node::Variable("name")],
+            };
+        };
+
+        let file_name = Path::new(path)
+            .file_name()
+            .and_then(|f| f.to_str())
+            .unwrap_or_default();
+        let dir = &path[..path.rfind(file_name).unwrap_or(path.len())];
+        let extension = match Path::new(file_name).extension().and_then(|e| e.to_str()) {
+            Some(e) => &file_name[file_name.len() - e.len() - 1..],
+            None => "",
+        };
+
+        let nodes = [
+            Node::Text(dir),
+            Node::Variable("name"),
+            Node::Text(extension),
+        ]
+        .into_iter()
+        .filter(|n| *n != Node::Text(""))
+        .collect();
+        Self { source, nodes }
+    }
 }
 
-fn parse_filtered_variable(v: &str) -> Result<FilteredVariable> {
-    let mut vals = v.split('|').map(|s| s.trim());
+/// splits a tag into its first word and the rest
+fn keyword(tag: &str) -> (&str, &str) {
+    match tag.split_once(char::is_whitespace) {
+        Some((keyword, rest)) => (keyword, rest.trim()),
+        None => (tag, ""),
+    }
+}
 
-    for v in vals.clone() {
-        validate_ident(v)?;
+/// parses a double quoted string literal, like `"hello \"world\""`
+fn parse_literal(s: &str) -> Result<String> {
+    let inner = s
+        .strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .ok_or_else(|| eyre!("expected a double quoted string, found `{s}`"))?;
+
+    let mut literal = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ ('"' | '\\')) => literal.push(c),
+                Some(c) => {
+                    literal.push('\\');
+                    literal.push(c);
+                }
+                None => return Err(eyre!("string literal {s} is unfinished")),
+            },
+            '"' => return Err(eyre!("unescaped quote in string literal {s}")),
+            c => literal.push(c),
+        }
     }
 
-    let name = vals.clone().collect::<Vec<_>>().join("_");
+    Ok(literal)
+}
+
+/// splits a valid string literal into the pieces of text it's made of, skipping the quotes
+/// and the backslashes used for escaping
+fn literal_pieces(s: &str) -> Vec<&str> {
+    let inner = &s[1..s.len() - 1];
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+
+    for (i, c) in inner.char_indices() {
+        if escaped {
+            escaped = false;
+            if c == '"' || c == '\\' {
+                // the escaped character starts the next piece
+                pieces.push(&inner[start..i - 1]);
+                start = i;
+            }
+        } else if c == '\\' {
+            escaped = true;
+        }
+    }
+    pieces.push(&inner[start..]);
+
+    pieces.retain(|p| !p.is_empty());
+    pieces
+}
+
+fn parse_filtered_variable(v: &str) -> Result<FilteredVariable<'_>> {
+    let mut vals = split_outside_strings(v, '|')?.into_iter().map(|s| s.trim());
+
     let variable = vals
         .next()
         .ok_or_else(|| eyre!("variable should have at least one ident"))?;
-    let filters = vals.collect();
+    validate_ident(variable)?;
+
+    let filters = vals.map(parse_filter_call).collect::<Result<Vec<_>>>()?;
+
+    let name = std::iter::once(variable)
+        .chain(filters.iter().map(|f| f.name))
+        .collect::<Vec<_>>()
+        .join("_");
 
     Ok(FilteredVariable {
         variable,
@@ -122,6 +1138,95 @@ fn parse_filtered_variable(v: &str) -> Result<FilteredVariable> {
     })
 }
 
+/// parses `filter` or `filter("string", 10)`
+fn parse_filter_call(s: &str) -> Result<FilterCall<'_>> {
+    let Some((name, rest)) = s.split_once('(') else {
+        validate_ident(s)?;
+        return Ok(FilterCall {
+            name: s,
+            arguments: Vec::new(),
+        });
+    };
+
+    let name = name.trim_end();
+    validate_ident(name)?;
+
+    let inner = rest
+        .strip_suffix(')')
+        .ok_or_else(|| eyre!("arguments for filter {name} are missing a closing parenthesis"))?;
+
+    let arguments = if inner.trim().is_empty() {
+        Vec::new()
+    } else {
+        split_outside_strings(inner, ',')?
+            .into_iter()
+            .map(|a| parse_filter_argument(a.trim()))
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    Ok(FilterCall { name, arguments })
+}
+
+fn parse_filter_argument(s: &str) -> Result<FilterArgument> {
+    if s.starts_with('"') {
+        Ok(FilterArgument::String(parse_literal(s)?))
+    } else if let Ok(i) = s.parse() {
+        Ok(FilterArgument::Integer(i))
+    } else {
+        Err(eyre!(
+            "filter argument `{s}` should be a double quoted string or an integer"
+        ))
+    }
+}
+
+/// splits `s` on `separator`, ignoring the ones inside of double quoted strings
+fn split_outside_strings(s: &str, separator: char) -> Result<Vec<&str>> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if in_string && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_string = !in_string;
+        } else if !in_string && c == separator {
+            parts.push(&s[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+
+    if in_string {
+        return Err(eyre!("string in `{s}` is unfinished"));
+    }
+
+    parts.push(&s[start..]);
+    Ok(parts)
+}
+
+/// where `needle` first appears in `s` outside of double quoted strings
+fn find_outside_strings(s: &str, needle: &str) -> Option<usize> {
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in s.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if in_string && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_string = !in_string;
+        } else if !in_string && s[i..].starts_with(needle) {
+            return Some(i);
+        }
+    }
+
+    None
+}
+
 fn validate_ident(s: &str) -> Result<()> {
     if s == "_" {
         return Err(eyre!("identifiers can't be a single underscore"));
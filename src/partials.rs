@@ -0,0 +1,60 @@
+use super::*;
+use std::{collections::BTreeMap, path::Path};
+
+/// sources for the templates included with `{{ include "name" }}`, by name
+#[derive(Debug, Default)]
+pub struct Partials {
+    sources: BTreeMap<String, String>,
+}
+
+/// used when parsing templates that don't include anything, like paths
+pub static NO_PARTIALS: Partials = Partials {
+    sources: BTreeMap::new(),
+};
+
+impl Partials {
+    /// loads every partial included by the template, and the ones included by those
+    pub fn load(
+        template: &str,
+        delimiters: &Delimiters,
+        read: impl Fn(&str) -> Result<String>,
+    ) -> Result<Self> {
+        let mut partials = Self::default();
+        let mut pending = included_names(template, delimiters).map_err(ParseError::into_report)?;
+
+        while let Some(name) = pending.pop() {
+            if partials.sources.contains_key(&name) {
+                continue;
+            }
+
+            let source = read(&name)?;
+            pending.extend(
+                included_names(&source, delimiters)
+                    .map_err(|e| e.in_partial(&name).into_report())?,
+            );
+            partials.sources.insert(name, source);
+        }
+
+        Ok(partials)
+    }
+
+    pub fn sources(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.sources.iter().map(|(n, s)| (n.as_str(), s.as_str()))
+    }
+}
+
+/// reads a partial from `.moho/partials/NAME`, or the source of the template at `.moho/NAME.mh`
+pub fn read_partial(name: &str) -> Result<String> {
+    let partial = Path::new(".moho/partials").join(name);
+    if partial.is_file() {
+        return Ok(std::fs::read_to_string(partial)?);
+    }
+
+    if Path::new(&format!(".moho/{name}.mh")).is_file() {
+        return Ok(Script::read(name)?.template);
+    }
+
+    Err(eyre!(
+        "couldn't find a partial at `.moho/partials/{name}` or a template called `{name}`"
+    ))
+}
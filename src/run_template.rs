@@ -0,0 +1,514 @@
+use crate::{create_template::apply_vars, filters::*};
+
+use super::*;
+use std::{
+    collections::HashMap,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+};
+
+/// renders a template without a shell, doing the same as running its script
+pub fn run_template(name: String, args: Vec<String>) -> Result<()> {
+    let Script { template, vars, .. } = Script::read(&name)?;
+    let delimiters = vars.delimiters()?;
+
+    let custom_filters = read_custom_filters()?;
+    let custom_filters = parse_custom_filters(&custom_filters);
+
+    let partials = Partials::load(&template, &delimiters, read_partial)?;
+    let mut parsed = parse_template(&template, &delimiters, &partials)?;
+    let default_path = vars.default_path.as_ref().map(|p| p.to_string_lossy());
+    parse_path(&mut parsed, default_path.as_deref())?;
+    validate_filters(&parsed, &custom_filters)?;
+    apply_vars(&mut parsed, &vars, &[], &[])?;
+
+    let Some((mut values, options)) = read_arguments(&parsed, &args)? else {
+        println!("{}", help(&name, &parsed).join("\n"));
+        return Ok(());
+    };
+
+    // like the script, the file is only written when the output isn't piped somewhere,
+    // except for generators, which always write their files, or when saying what to do with
+    // the existing ones
+    let mode = options.output.unwrap_or_else(|| {
+        if parsed.is_generator()
+            || options.overwrite != Overwrite::Ask
+            || io::stdout().is_terminal()
+        {
+            OutputMode::File
+        } else {
+            OutputMode::Stdout
+        }
+    });
+    let to_file = mode != OutputMode::Stdout;
+    if options.interactive || io::stdin().is_terminal() {
+        ask_values(&parsed, &mut values, options.interactive, to_file)?;
+    }
+
+    let inject = parsed.front_matter.as_ref().and_then(|f| f.inject.as_ref());
+    match render(&parsed, values, &custom_filters, to_file)? {
+        (Output::Single(out, _), _) if mode == OutputMode::Stdout => {
+            print!("{out}");
+            io::stdout().flush()?;
+        }
+        (Output::Single(out, path), _) => match inject {
+            Some(inject) => inject_into(&path, &out, inject, mode == OutputMode::DryRun)?,
+            None if mode == OutputMode::DryRun => show_files(&[(path, out)]),
+            None => write_files(&[(path, out)], options.overwrite)?,
+        },
+        (Output::Files(files), _) if mode == OutputMode::File => {
+            write_files(&files, options.overwrite)?
+        }
+        (Output::Files(files), _) => show_files(&files),
+    }
+
+    if let Some(front_matter) = &parsed.front_matter {
+        for message in &front_matter.messages {
+            eprintln!("{message}");
+        }
+    }
+
+    Ok(())
+}
+
+/// the values of the variables, like the shell variables of the script
+type Values = HashMap<String, String>;
+
+/// where the output goes, set by `--dry-run` and `--stdout`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    File,
+    DryRun,
+    Stdout,
+}
+
+/// what to do with files that already exist, set by `--force` and `--no-overwrite`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overwrite {
+    #[default]
+    Ask,
+    Yes,
+    No,
+}
+
+/// the options every script has, besides the template's variables
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Options {
+    /// `None` when it depends on where the output goes
+    pub output: Option<OutputMode>,
+    pub overwrite: Overwrite,
+    /// whether to ask for every value, not just the missing ones
+    pub interactive: bool,
+}
+
+/// reads `--var value`, `--flag` and `--no-flag` arguments, and the options every script has
+///
+/// returns `None` when the help should be shown, which the script also does for unknown
+/// arguments
+pub fn read_arguments(t: &Template, args: &[String]) -> Result<Option<(Values, Options)>> {
+    let mut values = Values::new();
+    for v in &t.variables {
+        let value = if v.kind == VariableType::Bool && v.default.as_deref() == Some("true") {
+            "true"
+        } else {
+            ""
+        };
+        values.insert(v.variable.to_string(), value.to_string());
+    }
+
+    let mut options = Options::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(option) = arg.strip_prefix("--") else {
+            return Ok(None);
+        };
+
+        let flag = |name: &str| {
+            t.variables
+                .iter()
+                .find(|v| v.variable == name && v.kind == VariableType::Bool)
+        };
+        if let Some(v) = flag(option) {
+            values.insert(v.variable.to_string(), "true".to_string());
+        } else if let Some(v) = option.strip_prefix("no-").and_then(flag) {
+            values.insert(v.variable.to_string(), String::new());
+        } else if let Some(v) = t.variables.iter().find(|v| v.variable == option) {
+            let value = args
+                .next()
+                .ok_or_else(|| eyre!("no value provided for {option}"))?;
+            values.insert(v.variable.to_string(), value.clone());
+        } else {
+            // like the script, the template's variables come first
+            match option {
+                "force" => options.overwrite = Overwrite::Yes,
+                "no-overwrite" => options.overwrite = Overwrite::No,
+                "dry-run" => options.output = Some(OutputMode::DryRun),
+                "stdout" => options.output = Some(OutputMode::Stdout),
+                "interactive" => options.interactive = true,
+                _ => return Ok(None),
+            }
+        }
+    }
+
+    Ok(Some((values, options)))
+}
+
+/// what rendering a template produces
+#[derive(Debug, PartialEq)]
+pub enum Output {
+    /// the output of a template, with the path it's written to unless it's printed
+    Single(String, PathBuf),
+    /// the files of a generator, with the paths they are written to
+    Files(Vec<(PathBuf, String)>),
+}
+
+/// whether the template can't be rendered without a name, like the script's name check
+fn needs_name(t: &Template, to_file: bool) -> bool {
+    t.is_name_used || (t.path_uses_name() && (to_file || t.is_generator()))
+}
+
+/// asks for the values that are missing, or for every value when `all` is set, with the
+/// current value or the default filled in
+fn ask_values(t: &Template, values: &mut Values, all: bool, to_file: bool) -> Result<()> {
+    let mut rl = rustyline::Editor::<()>::new()?;
+
+    for v in &t.variables {
+        // the name isn't used at all
+        if v.variable == "name" && !t.is_name_used && !t.path_uses_name() {
+            continue;
+        }
+
+        let value = values.get(v.variable).cloned().unwrap_or_default();
+        let required = if v.variable == "name" {
+            needs_name(t, to_file)
+        } else {
+            !v.optional && v.default.is_none() && v.kind != VariableType::Bool
+        };
+        if !(all || required && value.is_empty()) {
+            continue;
+        }
+
+        let prompt = format!("{}: ", prompt(v));
+        if v.kind == VariableType::Bool {
+            let initial = if value.is_empty() { "n" } else { "y" };
+            let readline = rl
+                .readline_with_initial(&prompt, (initial, ""))
+                .unwrap_or_default();
+            match readline.trim().to_lowercase().as_str() {
+                "y" | "yes" => values.insert(v.variable.to_string(), "true".to_string()),
+                "n" | "no" => values.insert(v.variable.to_string(), String::new()),
+                _ => None,
+            };
+            continue;
+        }
+
+        let initial = match &v.default {
+            Some(default) if value.is_empty() => default.clone(),
+            _ => value,
+        };
+        let readline = rl
+            .readline_with_initial(&prompt, (&initial, ""))
+            .unwrap_or_default();
+        let readline = readline.trim();
+        if !readline.is_empty() {
+            values.insert(v.variable.to_string(), readline.to_string());
+        }
+    }
+
+    Ok(())
+}
+
+/// fills in the defaults, checks the values, and renders the template
+///
+/// `to_file` is whether the output goes to a file, which needs a name
+pub fn render(
+    t: &Template,
+    mut values: Values,
+    custom_filters: &[CustomFilter],
+    to_file: bool,
+) -> Result<(Output, Values)> {
+    for v in &t.variables {
+        let value = values.entry(v.variable.to_string()).or_default();
+        if let Some(default) = &v.default {
+            if value.is_empty() && v.kind != VariableType::Bool {
+                *value = default.clone();
+            }
+        }
+    }
+
+    for v in &t.variables {
+        if v.variable == "name" || v.optional || v.kind == VariableType::Bool {
+            continue;
+        }
+        if values[v.variable].is_empty() {
+            return Err(eyre!("no value provided for {}", v.variable));
+        }
+    }
+    if values["name"].is_empty() && needs_name(t, to_file) {
+        return Err(eyre!("no value provided for name"));
+    }
+
+    for v in &t.variables {
+        let value = &values[v.variable];
+        match &v.kind {
+            VariableType::Integer if !is_integer(value) => {
+                return Err(eyre!("{} must be an integer, got '{value}'", v.variable))
+            }
+            VariableType::Enum(choices) if !value.is_empty() && !choices.contains(value) => {
+                return Err(eyre!(
+                    "{} must be one of {}, got '{value}'",
+                    v.variable,
+                    choices.join(", ")
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    let mut renderer = Renderer {
+        values,
+        custom_filters,
+    };
+    for f in &t.filtered {
+        renderer.filter(f)?;
+    }
+
+    let output = if t.is_generator() {
+        let mut files = Vec::new();
+        for file in &t.files {
+            let mut out = String::new();
+            renderer.render(&file.nodes, &mut out)?;
+            let mut path = String::new();
+            renderer.render(&file.path.nodes, &mut path)?;
+            files.push((PathBuf::from(path), out));
+        }
+        Output::Files(files)
+    } else {
+        let mut out = String::new();
+        renderer.render(&t.nodes, &mut out)?;
+        let mut path = String::new();
+        renderer.render(&t.path.nodes, &mut path)?;
+        Output::Single(out, PathBuf::from(path))
+    };
+
+    Ok((output, renderer.values))
+}
+
+/// same as the script's `-|*[!0-9-]*|?*-*` pattern, empty values are allowed
+fn is_integer(value: &str) -> bool {
+    value != "-"
+        && value.chars().all(|c| c.is_ascii_digit() || c == '-')
+        && !value.chars().skip(1).any(|c| c == '-')
+}
+
+struct Renderer<'f, 'c> {
+    values: Values,
+    custom_filters: &'f [CustomFilter<'c>],
+}
+
+impl Renderer<'_, '_> {
+    fn value(&self, variable: &str) -> &str {
+        self.values.get(variable).map_or("", String::as_str)
+    }
+
+    fn render(&mut self, nodes: &[Node], out: &mut String) -> Result<()> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Variable(v) => out.push_str(self.value(v)),
+                Node::Filtered(name) => out.push_str(self.value(name)),
+                Node::If {
+                    condition,
+                    then,
+                    otherwise,
+                } => {
+                    let holds = match condition {
+                        Condition::NotEmpty(v) => !self.value(v).is_empty(),
+                        Condition::Equals(v, value) => self.value(v) == value,
+                        Condition::NotEquals(v, value) => self.value(v) != value,
+                    };
+                    self.render(if holds { then } else { otherwise }, out)?;
+                }
+                Node::For {
+                    variable,
+                    list,
+                    body,
+                    filtered,
+                } => {
+                    // the body can change the values, so the list is copied
+                    let list = self.value(list).to_string();
+                    for item in split_list(&list) {
+                        self.values.insert(variable.to_string(), item.to_string());
+                        for f in filtered {
+                            self.filter(f)?;
+                        }
+                        self.render(body, out)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// computes a filtered variable, like `$(lower "$(upper "$var")")`
+    fn filter(&mut self, f: &FilteredVariable) -> Result<()> {
+        let mut value = self.value(f.variable).to_string();
+        for call in &f.filters {
+            let builtin = BUILTIN_FILTERS.iter().find(|b| b.name == call.name);
+            match builtin {
+                Some(builtin) if !self.custom_filters.iter().any(|c| c.name == call.name) => {
+                    value = (builtin.apply)(&value, &call.arguments);
+                }
+                _ => {
+                    return Err(eyre!(
+                        "filter `{}` is a custom filter, which needs a shell to run, \
+                         run the template's script instead",
+                        call.name
+                    ))
+                }
+            }
+
+            // command substitution removes the trailing newlines
+            value.truncate(value.trim_end_matches('\n').len());
+        }
+
+        self.values.insert(f.name.clone(), value);
+        Ok(())
+    }
+}
+
+/// splits a list on commas, like the shell does with `IFS=','`
+fn split_list(list: &str) -> Vec<&str> {
+    if list.is_empty() {
+        return Vec::new();
+    }
+
+    let mut items = list.split(',').collect::<Vec<_>>();
+    // a trailing comma doesn't start another item
+    if list.ends_with(',') {
+        items.pop();
+    }
+    items
+}
+
+/// prints the files with where they would be written, for `--dry-run`
+fn show_files(files: &[(PathBuf, String)]) {
+    for (i, (path, out)) in files.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("would write to {}:", path.display());
+        // like `printf '%s\n' "$(printf '%s' "$_moho_out")"`, which loses the trailing newlines
+        println!("{}", out.trim_end_matches('\n'));
+    }
+}
+
+/// writes the files, asking once before overwriting any of them unless told what to do
+fn write_files(files: &[(PathBuf, String)], overwrite: Overwrite) -> Result<()> {
+    let existing = files
+        .iter()
+        .filter(|(path, _)| path.is_file())
+        .collect::<Vec<_>>();
+    if !existing.is_empty() && overwrite == Overwrite::No {
+        if let [(path, _)] = &existing[..] {
+            return Err(eyre!("{} already exists", path.display()));
+        }
+        let list = existing
+            .iter()
+            .map(|(path, _)| format!("  {}", path.display()))
+            .collect::<Vec<_>>();
+        return Err(eyre!("these files already exist:\n{}", list.join("\n")));
+    }
+    if !existing.is_empty() && overwrite == Overwrite::Ask {
+        if files.len() == 1 {
+            print!("File already exists, overwrite? [y/N] ");
+        } else {
+            println!("These files already exist:");
+            for (path, _) in existing {
+                println!("  {}", path.display());
+            }
+            print!("Overwrite them? [y/N] ");
+        }
+        io::stdout().flush()?;
+
+        let mut response = String::new();
+        io::stdin().read_line(&mut response)?;
+        if !matches!(response.trim().to_lowercase().as_str(), "y" | "yes") {
+            println!("Stopping");
+            return Err(eyre!("the files were not overwritten"));
+        }
+    }
+
+    for (path, out) in files {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, out)?;
+        println!("created file at {}", path.display());
+    }
+
+    Ok(())
+}
+
+/// adds the output to an existing file, unless the file already has it
+///
+/// with `dry_run`, only shows what would be added
+pub fn inject_into(path: &Path, out: &str, inject: &Inject, dry_run: bool) -> Result<()> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| eyre!("couldn't read `{}`: {e}", path.display()))?;
+
+    // like the script, trailing newlines don't count
+    let added = out.trim_end_matches('\n');
+    if content.trim_end_matches('\n').contains(added) {
+        println!("{} already has it, skipping", path.display());
+        return Ok(());
+    }
+    if dry_run {
+        println!("would inject into {}:", path.display());
+        println!("{added}");
+        return Ok(());
+    }
+
+    let injected = match inject.pattern() {
+        Some((pattern, after)) => {
+            let regex = crate::ere::Ere::parse(pattern)?;
+            let mut injected = String::new();
+            let mut done = false;
+            // like awk, only `\n` ends lines
+            for line in content.split_terminator('\n') {
+                let matches = !done && regex.is_match(line);
+                if matches && !after {
+                    injected.push_str(added);
+                    injected.push('\n');
+                }
+                injected.push_str(line);
+                injected.push('\n');
+                if matches && after {
+                    injected.push_str(added);
+                    injected.push('\n');
+                }
+                done |= matches;
+            }
+
+            if !done {
+                return Err(eyre!("no line in {} matches '{pattern}'", path.display()));
+            }
+            injected
+        }
+        None => {
+            let mut injected = content;
+            if !injected.is_empty() && !injected.ends_with('\n') {
+                injected.push('\n');
+            }
+            injected.push_str(added);
+            injected.push('\n');
+            injected
+        }
+    };
+
+    std::fs::write(path, injected)?;
+    println!("injected into {}", path.display());
+
+    Ok(())
+}
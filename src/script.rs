@@ -0,0 +1,231 @@
+use super::*;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+
+/// what `moho edit` needs from a generated script, read from its text without running it
+#[derive(Debug)]
+pub struct Script {
+    /// the original source of the template
+    pub template: String,
+    pub vars: Vars,
+    pub status: Status,
+}
+
+/// version of the layout of generated scripts, bumped when moho can't read older ones the
+/// same way anymore
+///
+/// scripts without a header are format 1
+pub const SCRIPT_FORMAT: u32 = 2;
+
+/// how a script compares to what this version of moho generates
+#[derive(Debug, PartialEq)]
+pub enum Status {
+    Current,
+    /// the script doesn't match its hash, so it was changed after being generated
+    Modified,
+    /// generated by a version of moho that didn't add a header
+    Outdated,
+    /// generated by a newer version of moho, with a format this one doesn't know
+    Newer {
+        version: String,
+        format: u32,
+    },
+}
+
+impl Status {
+    /// checks the header of the script, written by `add_header`
+    pub fn of(script: &str) -> Self {
+        let mut version = None;
+        let mut format = None;
+        let mut hash = None;
+        let mut offset = 0;
+        for line in script.split_inclusive('\n').take(5) {
+            offset += line.len();
+            let line = line.trim_end();
+            if let Some(v) = line.strip_prefix("# moho-version: ") {
+                version = Some(v);
+            } else if let Some(f) = line.strip_prefix("# moho-format: ") {
+                format = f.parse::<u32>().ok();
+            } else if let Some(h) = line.strip_prefix("# moho-hash: ") {
+                hash = Some((h, offset));
+                break;
+            }
+        }
+
+        match (format, hash) {
+            (Some(format), _) if format > SCRIPT_FORMAT => Status::Newer {
+                version: version.unwrap_or("unknown").to_string(),
+                format,
+            },
+            (Some(_), Some((hash, offset))) if hash == fnv_hash(&script[offset..]) => {
+                Status::Current
+            }
+            (Some(_), _) => Status::Modified,
+            (None, _) => Status::Outdated,
+        }
+    }
+
+    /// a warning about the script, unless it's current
+    pub fn warning(&self, name: &str) -> Option<String> {
+        match self {
+            Status::Current => None,
+            Status::Modified => Some(format!(
+                "template `{name}` was changed by hand after being generated, \
+                 saving it with `moho edit` will undo those changes"
+            )),
+            Status::Outdated => Some(format!(
+                "template `{name}` was generated by an older version of moho, \
+                 saving it with `moho edit` will update it"
+            )),
+            Status::Newer { version, format } => Some(format!(
+                "template `{name}` was generated by moho {version} (script format {format}), \
+                 but this is moho {} (script format {SCRIPT_FORMAT}), it might not be read correctly",
+                env!("CARGO_PKG_VERSION")
+            )),
+        }
+    }
+}
+
+/// adds the header with the moho version, script format, and hash of the rest of the script
+/// after the shebang
+pub fn add_header(script: &str) -> String {
+    let (shebang, body) = script.split_once('\n').unwrap_or((script, ""));
+    format!(
+        "{shebang}
+# generated by moho, use `moho edit` instead of changing it by hand
+# moho-version: {}
+# moho-format: {SCRIPT_FORMAT}
+# moho-hash: {}
+{body}",
+        env!("CARGO_PKG_VERSION"),
+        fnv_hash(body)
+    )
+}
+
+/// 64 bit FNV-1a, as hex. not meant to be secure, only to notice changes
+fn fnv_hash(s: &str) -> String {
+    let hash = s.bytes().fold(0xcbf29ce484222325_u64, |hash, b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{hash:016x}")
+}
+
+/// the metadata printed by `get-vars`, also used for `moho create --vars-file`
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct Vars {
+    pub default_path: Option<PathBuf>,
+    pub delimiters: Option<(String, String)>,
+    pub defaults: HashMap<String, String>,
+    pub descriptions: HashMap<String, String>,
+    /// missing in scripts generated before variables had types
+    pub types: HashMap<String, String>,
+}
+
+impl Vars {
+    /// the template's delimiters, `{{ }}` unless others were saved
+    pub fn delimiters(&self) -> Result<Delimiters> {
+        match &self.delimiters {
+            Some((start, end)) => Delimiters::new(start, end),
+            None => Ok(Delimiters::default()),
+        }
+    }
+}
+
+/// heredoc delimiters of the payload, a `_` is added to them if the payload contains them
+pub const TEMPLATE_DELIMITER: &str = "MOHO_TEMPLATE";
+pub const VARS_DELIMITER: &str = "MOHO_VARS";
+
+impl Script {
+    /// reads the script at `.moho/NAME.mh`
+    pub fn read(name: &str) -> Result<Self> {
+        let path = format!(".moho/{name}.mh");
+        let source = std::fs::read_to_string(&path)
+            .map_err(|e| eyre!("couldn't read template `{path}`: {e}"))?;
+
+        Self::parse(&source).map_err(|e| e.wrap_err(format!("`{path}` isn't a valid moho script")))
+    }
+
+    pub fn parse(source: &str) -> Result<Self> {
+        // the vars are searched after the template, since the template could contain anything
+        let (template, rest) = payload(source, "get-template")?;
+        let (vars, _) = payload(rest, "get-vars")?;
+        let vars = toml::from_str(&vars).map_err(|e| eyre!("its variables are invalid: {e}"))?;
+
+        Ok(Self {
+            template,
+            vars,
+            status: Status::of(source),
+        })
+    }
+}
+
+/// a heredoc that's safe to print with `cat`, with a delimiter that's not in `content`
+pub fn heredoc(delimiter: &str, content: &str) -> String {
+    let mut delimiter = delimiter.to_string();
+    while content.lines().any(|l| l == delimiter) {
+        delimiter.push('_');
+    }
+
+    format!("cat <<'{delimiter}'\n{content}\n{delimiter}\n")
+}
+
+/// reads what the script prints for `command`, from the first block that starts with
+/// `if [ "$1" = "command" ]; then`, returning it and the rest of the script
+fn payload<'a>(source: &'a str, command: &str) -> Result<(String, &'a str)> {
+    let start = format!("if [ \"$1\" = \"{command}\" ]; then\n");
+    let block = source
+        .find(&start)
+        .map(|i| &source[i + start.len()..])
+        .ok_or_else(|| eyre!("the `{command}` section is missing"))?;
+
+    if let Some(rest) = block.strip_prefix("cat <<'") {
+        let (delimiter, rest) = rest
+            .split_once("'\n")
+            .ok_or_else(|| eyre!("the `{command}` section is unfinished"))?;
+
+        // the content ends with the newline before the delimiter's line
+        let mut content = String::new();
+        let mut offset = 0;
+        for line in rest.split_inclusive('\n') {
+            offset += line.len();
+            if line.trim_end_matches('\n') == delimiter {
+                content.pop();
+                return Ok((content, &rest[offset..]));
+            }
+            content.push_str(line);
+        }
+    } else if let Some(rest) = block
+        .strip_prefix("printf '%s\\n' \"")
+        .or_else(|| block.strip_prefix("echo \""))
+    {
+        // scripts from older versions print a double quoted string
+        return unescape(rest).ok_or_else(|| eyre!("the `{command}` section is unfinished"));
+    }
+
+    Err(eyre!("the `{command}` section is unfinished"))
+}
+
+/// reads a double quoted shell string up to its closing quote, undoing `helpers::escape`,
+/// and returns it with the rest of `s`
+fn unescape(s: &str) -> Option<(String, &str)> {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((out, &s[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                c @ ('"' | '\\' | '$' | '`') => out.push(c),
+                // a backslash followed by a newline is removed by the shell
+                '\n' => {}
+                c => {
+                    out.push('\\');
+                    out.push(c);
+                }
+            },
+            c => out.push(c),
+        }
+    }
+
+    None
+}
@@ -9,9 +9,22 @@ fn verify_cli() {
 
 #[test]
 fn parse() -> Result<()> {
-    let out = parse_template("hello {{ hi }} {{ hey | upper }} hii")?;
+    let out = parse_template(
+        "hello {{ hi }} {{ hey | upper }} hii",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
 
-    assert_eq!(out.generated, "hello ${hi} ${hey_upper} hii");
+    assert_eq!(
+        out.nodes,
+        vec![
+            Node::Text("hello "),
+            Node::Variable("hi"),
+            Node::Text(" "),
+            Node::Filtered("hey_upper".to_string()),
+            Node::Text(" hii"),
+        ]
+    );
 
     // check variables
     assert_eq!(
@@ -27,7 +40,10 @@ fn parse() -> Result<()> {
         out.filtered,
         vec![FilteredVariable {
             variable: "hey",
-            filters: vec!["upper"],
+            filters: vec![FilterCall {
+                name: "upper",
+                arguments: vec![]
+            }],
             name: "hey_upper".to_string()
         }]
     );
@@ -37,15 +53,25 @@ fn parse() -> Result<()> {
 
 #[test]
 fn parse_and_generate() -> Result<()> {
-    let mut out = parse_template("hello {{ hi }} {{ hey | upper }} hii")?;
+    let mut out = parse_template(
+        "hello {{ hi }} {{ hey | upper }} hii",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
 
     out.variables.first_mut().unwrap().default = Some("meooow".to_string());
     out.variables.first_mut().unwrap().description = Some("this is a description".to_string());
+    parse_path(&mut out, Some("./folder/name.rs"))?;
 
-    let out = generate_bash_script("test", out, Some("./folder/name.rs".into()));
+    let out = generate_bash_script("test", out, &[]);
+
+    // the header changes with every version, it's tested in `script_headers`
+    let mut lines = out.split_inclusive('\n').collect::<Vec<_>>();
+    assert!(lines[1].starts_with("# generated by moho"));
+    lines.drain(1..5);
 
     assert_eq!(
-        out,
+        lines.concat(),
         r#"#!/bin/sh
 set -e
 
@@ -57,6 +83,9 @@ if [ ! "$1" = "get-template" ] && [ ! "$1" = "get-vars" ]; then
 hi=''
 hey=''
 name=''
+_moho_output=''
+_moho_overwrite='ask'
+_moho_interactive=''
 
 # parse arguments
 while test $# -gt 0; do
@@ -76,20 +105,83 @@ while test $# -gt 0; do
       name="$1"
       shift
       ;;
+    --force)
+      shift
+      _moho_overwrite='yes'
+      ;;
+    --no-overwrite)
+      shift
+      _moho_overwrite='no'
+      ;;
+    --dry-run)
+      shift
+      _moho_output='dry-run'
+      ;;
+    --stdout)
+      shift
+      _moho_output='stdout'
+      ;;
+    --interactive)
+      shift
+      _moho_interactive='true'
+      ;;
     *)
       echo "test:"
       echo "generates file at ./folder/NAME.rs"
       echo ""
       echo "options:"
-      echo "-h, --help      show brief help"
-      echo "--name NAME     filename (without extension)"
-      echo "--hi HI         this is a description"
+      echo "-h, --help         show brief help"
+      echo "--name NAME        filename (without extension)"
+      echo "--hi HI            this is a description"
       echo "--hey HEY"
+      echo "--force            write files even when piped, overwriting them without asking"
+      echo "--no-overwrite     write files even when piped, failing if they exist"
+      echo "--dry-run          show where the output would go, without writing anything"
+      echo "--stdout           print the output instead of writing it"
+      echo "--interactive      ask for every value, with the defaults filled in"
       exit 0
       ;;
   esac
 done
 
+# where the output goes, files aren't written when it's piped, unless saying what to do
+# with the existing ones
+if [ -z "$_moho_output" ] ; then
+  if [ -t 1 ] || [ "$_moho_overwrite" != 'ask' ] ; then
+    _moho_output='file'
+  else
+    _moho_output='stdout'
+  fi
+fi
+
+# ask for missing values on a terminal, or for every value with --interactive
+_moho_ask() {
+  if [ -n "$2" ] ; then
+    printf '%s [%s]: ' "$1" "$2" >&2
+  else
+    printf '%s: ' "$1" >&2
+  fi
+  read -r _moho_answer || _moho_answer=''
+}
+if [ -n "$_moho_interactive" ] ; then
+  _moho_ask "hi (this is a description)" "${hi:-"meooow"}"
+  if [ -n "$_moho_answer" ] ; then
+    hi="$_moho_answer"
+  fi
+fi
+if [ -n "$_moho_interactive" ] || { [ -z "$hey" ] && [ -t 0 ]; } ; then
+  _moho_ask "hey" "$hey"
+  if [ -n "$_moho_answer" ] ; then
+    hey="$_moho_answer"
+  fi
+fi
+if [ -n "$_moho_interactive" ] || { [ -z "$name" ] && [ "$_moho_output" != 'stdout' ] && [ -t 0 ]; } ; then
+  _moho_ask "name (filename without extension)" "$name"
+  if [ -n "$_moho_answer" ] ; then
+    name="$_moho_answer"
+  fi
+fi
+
 # set variable defaults
 hi=${hi:-"meooow"}
 
@@ -102,62 +194,78 @@ if [ -z "$hey" ]; then
   echo "Error: No value provided for hey"
   exit 1
 fi
-if [ -z "$name" ] && [ -t 1 ]; then
+if [ -z "$name" ] && [ "$_moho_output" != 'stdout' ]; then
   echo "Error: No value provided for name"
   exit 1
 fi
 
 # filters
 upper() {
-  echo $(echo "$1" | tr '[:lower:]' '[:upper:]')
+  printf '%s\n' "$1" | tr '[:lower:]' '[:upper:]'
 }
 
 # filtered variables
 hey_upper=$(upper "$hey")
 
-out="hello ${hi} ${hey_upper} hii"
-if [ -t 1 ] ; then
-  mkdir -p "./folder"
+_moho_out="hello ${hi} ${hey_upper} hii"
+_moho_path="./folder/${name}.rs"
+if [ "$_moho_output" = 'file' ] ; then
+  mkdir -p "$(dirname "$_moho_path")"
 
   # check if file exists
-  if [ -f "./folder/${name}.rs" ] ; then
-     read -r -p "File already exists, overwrite? [y/N] " response
-     case "$response" in
-       [yY][eE][sS]|[yY])
-         ;;
-       *)
-         echo "Stopping"
-         exit 1
-         ;;
-     esac
+  if [ -f "$_moho_path" ] ; then
+    case "$_moho_overwrite" in
+      no)
+        echo "Error: $_moho_path already exists"
+        exit 1
+        ;;
+      ask)
+        read -r -p "File already exists, overwrite? [y/N] " response
+        case "$response" in
+          [yY][eE][sS]|[yY])
+            ;;
+          *)
+            echo "Stopping"
+            exit 1
+            ;;
+        esac
+        ;;
+    esac
   fi
 
-  echo "$out" > "./folder/${name}.rs"
-  echo "created file at ./folder/${name}.rs";
+  printf '%s' "$_moho_out" > "$_moho_path"
+  echo "created file at $_moho_path";
+elif [ "$_moho_output" = 'dry-run' ] ; then
+  echo "would write to $_moho_path:"
+  printf '%s\n' "$(printf '%s' "$_moho_out")"
 else
-  echo "$out"
+  printf '%s' "$_moho_out"
 fi
 
 # end normal block
 fi
 
-# template editing section
+# template editing section, read by `moho edit` without running the script
 
 if [ "$1" = "get-template" ]; then
-echo "hello {{ hi }} {{ hey | upper }} hii"
+cat <<'MOHO_TEMPLATE'
+hello {{ hi }} {{ hey | upper }} hii
+MOHO_TEMPLATE
 fi
 
 if [ "$1" = "get-vars" ]; then
-echo "
-default_path=\"./folder/name.rs\"
+cat <<'MOHO_VARS'
+default_path="./folder/name.rs"
 
 [defaults]
-hi=\"meooow\"
+hi="meooow"
+
+[types]
 
 [descriptions]
-hi=\"this is a description\"
+hi="this is a description"
 
-"
+MOHO_VARS
 fi
 "#
     );
@@ -167,24 +275,48 @@ fi
 
 #[test]
 fn invalid_variables() {
-    let out = parse_template("this is a {{ demo that breaks }} because the variables are invalid");
+    let out = parse_template(
+        "this is a {{ demo that breaks }} because the variables are invalid",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    );
     assert!(out.is_err());
 }
 
 #[test]
 fn escapes() -> Result<()> {
-    let out = parse_template(r#" this "string" should be $escaped "#)?;
-    assert_eq!(out.generated, r#" this \"string\" should be \$escaped "#);
+    let out = parse_template(
+        r#" this "string" should be $escaped "#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    assert_eq!(
+        generate_output(&out.nodes),
+        r#"_moho_out=" this \"string\" should be \$escaped "
+"#
+    );
 
-    let out = parse_template(r#" \$ double escape "#)?;
-    assert_eq!(out.generated, r#" \\\$ double escape "#);
+    let out = parse_template(
+        r#" \$ double escape "#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    assert_eq!(
+        generate_output(&out.nodes),
+        r#"_moho_out=" \\\$ double escape "
+"#
+    );
 
     Ok(())
 }
 
 #[test]
 fn unfinished_variable() -> Result<()> {
-    let out = parse_template(r#" this variable is {{ unfinished "#);
+    let out = parse_template(
+        r#" this variable is {{ unfinished "#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    );
     assert!(out.is_err());
 
     Ok(())
@@ -192,8 +324,2107 @@ fn unfinished_variable() -> Result<()> {
 
 #[test]
 fn nested_variable() -> Result<()> {
-    let out = parse_template(r#" this variable has {{ nesting {{ inside }} }} "#);
+    let out = parse_template(
+        r#" this variable has {{ nesting {{ inside }} }} "#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    );
     assert!(out.is_err());
 
     Ok(())
 }
+
+/// runs the script with `sh`, checking that `moho run` gives the same output
+fn run_script(script: &str, args: &[&str]) -> Result<String> {
+    let output = std::process::Command::new("/bin/sh")
+        .arg("-c")
+        .arg(script)
+        .arg("test")
+        .args(args)
+        .output()?;
+
+    let shell = if output.status.success() {
+        Ok(String::from_utf8(output.stdout)?)
+    } else {
+        Err(eyre!(
+            "script failed: {}",
+            String::from_utf8_lossy(&output.stdout)
+        ))
+    };
+
+    let rust = run_in_rust(script, args);
+    match (&shell, rust) {
+        (Ok(shell), Some(Ok(rust))) => {
+            assert_eq!(shell, &rust, "`moho run` differs from the script")
+        }
+        (Ok(_), Some(Err(e))) => panic!("`moho run` failed, but the script didn't: {e}"),
+        (Err(e), Some(Ok(_))) => panic!("the script failed, but `moho run` didn't: {e}"),
+        _ => {}
+    }
+
+    shell
+}
+
+/// runs the script with `sh` in `dir`, for the scripts that write files
+fn run_in(dir: &std::path::Path, script: &str, args: &[&str]) -> Result<(bool, String)> {
+    let output = std::process::Command::new("/bin/sh")
+        .current_dir(dir)
+        .arg("-c")
+        .arg(script)
+        .arg("test")
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .output()?;
+    Ok((output.status.success(), String::from_utf8(output.stdout)?))
+}
+
+/// renders the template of the script like `moho run` does, unless it needs partials or
+/// custom filters, which aren't available from the script alone
+fn run_in_rust(script: &str, args: &[&str]) -> Option<Result<String>> {
+    use crate::{create_template::apply_vars, run_template::*};
+
+    if matches!(args.first(), Some(&"get-template" | &"get-vars")) {
+        return None;
+    }
+
+    // anything else going wrong is a bug, not a reason to skip the check
+    let fail = |e: color_eyre::Report| -> ! { panic!("`moho run` couldn't read the script: {e}") };
+    let Script { template, vars, .. } = Script::parse(script).unwrap_or_else(|e| fail(e));
+    let delimiters = vars.delimiters().unwrap_or_else(|e| fail(e));
+    if !included_names(&template, &delimiters)
+        .unwrap_or_else(|e| fail(e.into_report()))
+        .is_empty()
+    {
+        return None;
+    }
+    let mut t = parse_template(&template, &delimiters, &NO_PARTIALS).unwrap_or_else(|e| fail(e));
+    // generators write files instead of printing them
+    if t.is_generator() {
+        return None;
+    }
+    let default_path = vars.default_path.as_ref().map(|p| p.to_string_lossy());
+    parse_path(&mut t, default_path.as_deref()).unwrap_or_else(|e| fail(e));
+    apply_vars(&mut t, &vars, &[], &[]).unwrap_or_else(|e| fail(e));
+    if t.used_filters()
+        .iter()
+        .any(|f| !crate::filters::BUILTIN_FILTERS.iter().any(|b| b.name == *f))
+    {
+        return None;
+    }
+
+    let args = args.iter().map(|a| a.to_string()).collect::<Vec<_>>();
+    Some(match read_arguments(&t, &args) {
+        // dry runs print where the output would go, not just the output
+        Ok(Some((_, options))) if options.output == Some(OutputMode::DryRun) => return None,
+        Ok(Some((values, _))) => render(&t, values, &[], false).map(|(out, _)| match out {
+            Output::Single(out, _) => out,
+            Output::Files(_) => unreachable!("generators were skipped above"),
+        }),
+        Ok(None) => Ok(help("test", &t).join("\n") + "\n"),
+        Err(e) => Err(e),
+    })
+}
+
+#[test]
+fn parse_conditionals() -> Result<()> {
+    let out = parse_template(
+        r#"a{{ if model }}b {{ model }}{{ else }}c{{ end }}{{ if kind == "x" }}d{{ end }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+
+    assert_eq!(
+        out.nodes,
+        vec![
+            Node::Text("a"),
+            Node::If {
+                condition: Condition::NotEmpty("model"),
+                then: vec![Node::Text("b "), Node::Variable("model")],
+                otherwise: vec![Node::Text("c")],
+            },
+            Node::If {
+                condition: Condition::Equals("kind", "x".to_string()),
+                then: vec![Node::Text("d")],
+                otherwise: vec![],
+            },
+        ]
+    );
+
+    // variables used in conditions don't need a value
+    assert!(out
+        .variables
+        .iter()
+        .all(|v| v.optional || v.variable == "name"));
+
+    // the operators can be in the value
+    let out = parse_template(
+        r#"{{ if a != "x==y" }}b{{ end }}{{ if c == "d!=e" }}f{{ end }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    assert_eq!(
+        out.nodes,
+        vec![
+            Node::If {
+                condition: Condition::NotEquals("a", "x==y".to_string()),
+                then: vec![Node::Text("b")],
+                otherwise: vec![],
+            },
+            Node::If {
+                condition: Condition::Equals("c", "d!=e".to_string()),
+                then: vec![Node::Text("f")],
+                otherwise: vec![],
+            },
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn generate_conditionals() -> Result<()> {
+    let out = parse_template(
+        r#"{{ if model }}use {{ model }};{{ else }}{{ end }}
+{{ if kind != "a \"b\"" }}{{ if model }}nested{{ end }}{{ end }}end"#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+
+    assert_eq!(
+        generate_output(&out.nodes),
+        r#"_moho_out=""
+if [ -n "$model" ]; then
+  _moho_out="${_moho_out}use ${model};"
+fi
+_moho_out="${_moho_out}
+"
+if [ "$kind" != "a \"b\"" ]; then
+  if [ -n "$model" ]; then
+    _moho_out="${_moho_out}nested"
+  fi
+fi
+_moho_out="${_moho_out}end"
+"#
+    );
+
+    Ok(())
+}
+
+#[test]
+fn run_conditionals() -> Result<()> {
+    let t = parse_template(
+        r#"{{ if model }}model {{ model }}{{ else }}no model{{ end }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    let script = generate_bash_script("test", t, &[]);
+
+    assert_eq!(run_script(&script, &["--model", "User"])?, "model User");
+    assert_eq!(run_script(&script, &[])?, "no model");
+
+    let t = parse_template(
+        r#"{{ if kind == "api" }}api{{ else }}web{{ end }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    let script = generate_bash_script("test", t, &[]);
+
+    assert_eq!(run_script(&script, &["--kind", "api"])?, "api");
+    assert_eq!(run_script(&script, &["--kind", "other"])?, "web");
+
+    Ok(())
+}
+
+#[test]
+fn invalid_conditionals() {
+    assert!(parse_template(
+        "{{ if model }} unclosed",
+        &Delimiters::default(),
+        &NO_PARTIALS
+    )
+    .is_err());
+    assert!(parse_template(
+        "{{ if model }} a {{ else }} b",
+        &Delimiters::default(),
+        &NO_PARTIALS
+    )
+    .is_err());
+    assert!(parse_template("stray {{ end }}", &Delimiters::default(), &NO_PARTIALS).is_err());
+    assert!(parse_template("stray {{ else }}", &Delimiters::default(), &NO_PARTIALS).is_err());
+    assert!(parse_template("{{ if }} a {{ end }}", &Delimiters::default(), &NO_PARTIALS).is_err());
+    assert!(parse_template(
+        "{{ if model == unquoted }} a {{ end }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )
+    .is_err());
+}
+
+#[test]
+fn parse_loops() -> Result<()> {
+    let out = parse_template(
+        "{{ for field in fields }}{{ field }} {{ field | upper }}{{ end }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+
+    assert_eq!(
+        out.nodes,
+        vec![Node::For {
+            variable: "field",
+            list: "fields",
+            body: vec![
+                Node::Variable("field"),
+                Node::Text(" "),
+                Node::Filtered("field_upper".to_string()),
+            ],
+            filtered: vec![FilteredVariable {
+                variable: "field",
+                filters: vec![FilterCall {
+                    name: "upper",
+                    arguments: vec![]
+                }],
+                name: "field_upper".to_string()
+            }],
+        }]
+    );
+
+    assert_eq!(out.used_filters(), vec!["upper"]);
+
+    // the loop variable is not an argument
+    assert_eq!(
+        out.variables
+            .into_iter()
+            .map(|v| v.variable)
+            .collect::<Vec<_>>(),
+        vec!["fields", "name"]
+    );
+    assert!(out.filtered.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn generate_loops() -> Result<()> {
+    let out = parse_template(
+        "a\n{{ for field in fields }}{{ field | upper }},{{ end }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+
+    assert_eq!(
+        generate_output(&out.nodes),
+        r#"_moho_out="a
+"
+IFS=','; set -f
+for field in $fields; do
+  unset IFS; set +f
+  field_upper=$(upper "$field")
+  _moho_out="${_moho_out}${field_upper},"
+done
+unset IFS; set +f
+"#
+    );
+
+    Ok(())
+}
+
+#[test]
+fn run_loops() -> Result<()> {
+    let t = parse_template(
+        "{{ for a in xs }}{{ for b in ys }}{{ a | upper }}{{ b }} {{ end }}{{ end }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    let script = generate_bash_script("test", t, &[]);
+
+    assert_eq!(
+        run_script(&script, &["--xs", "a,b", "--ys", "1,*"])?,
+        "A1 A* B1 B* "
+    );
+
+    // the output is built in a variable of its own, so templates can use any name
+    let t = parse_template(
+        "{{ if x }}A{{ end }}-{{ out }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    let script = generate_bash_script("test", t, &[]);
+    assert_eq!(run_script(&script, &["--x", "1", "--out", "Z"])?, "A-Z");
+
+    let t = parse_template(
+        "{{ for out in items }}[{{ out }}]{{ end }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    let script = generate_bash_script("test", t, &[]);
+    assert_eq!(run_script(&script, &["--items", "a,b"])?, "[a][b]");
+
+    Ok(())
+}
+
+#[test]
+fn invalid_loops() {
+    assert!(parse_template(
+        "{{ for field in fields }} unclosed",
+        &Delimiters::default(),
+        &NO_PARTIALS
+    )
+    .is_err());
+    assert!(parse_template(
+        "{{ for field fields }}{{ end }}",
+        &Delimiters::default(),
+        &NO_PARTIALS
+    )
+    .is_err());
+    assert!(parse_template(
+        "{{ for a in xs }}{{ for a in ys }}{{ end }}{{ end }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )
+    .is_err());
+    // loop variables can't shadow other variables
+    assert!(parse_template(
+        "{{ field }}{{ for field in fields }}{{ end }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )
+    .is_err());
+}
+
+#[test]
+fn builtin_filters() -> Result<()> {
+    let cases = [
+        ("lower", "Hello World", "hello world"),
+        ("upper", "Hello World", "HELLO WORLD"),
+        ("snake", "HTTPServerError", "http_server_error"),
+        ("snake", "user-profile name", "user_profile_name"),
+        ("camel", "user_profile", "userProfile"),
+        ("pascal", "user profile", "UserProfile"),
+        ("kebab", "UserProfile", "user-profile"),
+        ("screaming_snake", "userProfile", "USER_PROFILE"),
+        ("title", "user_profile", "User Profile"),
+        ("capitalize", "user profile", "User profile"),
+        ("plural", "user", "users"),
+        ("plural", "category", "categories"),
+        ("plural", "day", "days"),
+        ("plural", "box", "boxes"),
+        ("plural", "match", "matches"),
+        ("singular", "categories", "category"),
+        ("singular", "boxes", "box"),
+        ("singular", "addresses", "address"),
+        ("singular", "houses", "house"),
+        ("singular", "class", "class"),
+        ("trim", "  padded \t", "padded"),
+    ];
+
+    for (filter, input, expected) in cases {
+        let template = format!("{{{{ v | {filter} }}}}");
+        let script = generate_bash_script(
+            "test",
+            parse_template(&template, &Delimiters::default(), &NO_PARTIALS)?,
+            &[],
+        );
+
+        assert_eq!(
+            run_script(&script, &["--v", input])?,
+            expected,
+            "{filter} {input:?}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn only_used_filters_are_generated() -> Result<()> {
+    let t = parse_template(
+        "{{ a | lower }} {{ b | snake | upper }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    let script = generate_bash_script("test", t, &[]);
+
+    assert!(script.contains("\nlower() {"));
+    assert!(script.contains("\nsnake() {"));
+    assert!(script.contains("\nupper() {"));
+    assert!(script.contains("\n_moho_words() {"));
+    assert!(!script.contains("\ncamel() {"));
+
+    let t = parse_template("{{ a | lower }}", &Delimiters::default(), &NO_PARTIALS)?;
+    let script = generate_bash_script("test", t, &[]);
+
+    assert!(!script.contains("_moho_words"));
+
+    Ok(())
+}
+
+#[test]
+fn unknown_filters() -> Result<()> {
+    use crate::filters::*;
+
+    let t = parse_template(
+        "{{ model | snake | plural }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    assert!(validate_filters(&t, &[]).is_ok());
+
+    let t = parse_template("{{ model | uper }}", &Delimiters::default(), &NO_PARTIALS)?;
+    let err = validate_filters(&t, &[]).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "unknown filter `uper`, did you mean `upper`?"
+    );
+
+    // filters used on loop variables are checked too
+    let t = parse_template(
+        "{{ for f in fields }}{{ f | reverse }}{{ end }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    let err = validate_filters(&t, &[]).unwrap_err();
+    assert_eq!(err.to_string(), "unknown filter `reverse`");
+
+    let custom = parse_custom_filters("#!/bin/sh\n\nreverse() {\n  rev\n}\n");
+    assert!(validate_filters(&t, &custom).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn custom_filters() -> Result<()> {
+    use crate::filters::*;
+
+    let source = r#"#!/bin/sh
+set -e
+
+# write your custom filters here
+shout() {
+  printf '%s!\n' "$(upper "$1")"
+}
+
+# a helper, with braces in strings and comments }
+_wrap() {
+  printf '{%s}\n' "$1" # }
+}
+
+braces () {
+  _wrap "$1"
+}
+
+unused() { echo "not used"; }
+"#;
+
+    let custom = parse_custom_filters(source);
+    assert_eq!(
+        custom.iter().map(|f| f.name).collect::<Vec<_>>(),
+        vec!["shout", "_wrap", "braces", "unused"]
+    );
+
+    let t = parse_template(
+        "{{ a | shout }} {{ a | braces }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    validate_filters(&t, &custom)?;
+    let script = generate_bash_script("test", t, &custom);
+
+    // only the used filters, and the functions they call, are included
+    assert!(script.contains("\nupper() {"));
+    assert!(script.contains("\n_wrap() {"));
+    assert!(!script.contains("unused"));
+
+    assert_eq!(run_script(&script, &["--a", "hi"])?, "HI! {hi}");
+
+    Ok(())
+}
+
+#[test]
+fn parse_filter_arguments() -> Result<()> {
+    let out = parse_template(
+        r#"{{ a | replace("_", "|") | pad(10, "\"") }} {{ a | replace("-", " ") }} {{ a|replace("_","|")|pad(10,"\"") }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+
+    assert_eq!(
+        out.filtered,
+        vec![
+            FilteredVariable {
+                variable: "a",
+                filters: vec![
+                    FilterCall {
+                        name: "replace",
+                        arguments: vec![
+                            FilterArgument::String("_".to_string()),
+                            FilterArgument::String("|".to_string())
+                        ]
+                    },
+                    FilterCall {
+                        name: "pad",
+                        arguments: vec![
+                            FilterArgument::Integer(10),
+                            FilterArgument::String("\"".to_string())
+                        ]
+                    }
+                ],
+                name: "a_replace_pad".to_string()
+            },
+            FilteredVariable {
+                variable: "a",
+                filters: vec![FilterCall {
+                    name: "replace",
+                    arguments: vec![
+                        FilterArgument::String("-".to_string()),
+                        FilterArgument::String(" ".to_string())
+                    ]
+                }],
+                name: "a_replace".to_string()
+            },
+        ]
+    );
+
+    assert!(parse_template(
+        r#"{{ a | replace("_", }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS
+    )
+    .is_err());
+    assert!(parse_template(
+        r#"{{ a | replace("_" }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS
+    )
+    .is_err());
+    assert!(parse_template(
+        r#"{{ a | replace(_) }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS
+    )
+    .is_err());
+    assert!(parse_template(
+        r#"{{ a | replace("_) }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn filter_arguments_are_validated() -> Result<()> {
+    use crate::filters::*;
+
+    let t = parse_template(
+        r#"{{ a | truncate("10") }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    assert_eq!(
+        validate_filters(&t, &[]).unwrap_err().to_string(),
+        "filter `truncate` is used like `truncate(length: integer)`"
+    );
+
+    let t = parse_template(
+        r#"{{ a | upper(1) }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    assert_eq!(
+        validate_filters(&t, &[]).unwrap_err().to_string(),
+        "filter `upper` takes no arguments"
+    );
+
+    let t = parse_template(
+        r#"{{ a | pad(10) }} {{ a | pad(10, "-") }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    assert!(validate_filters(&t, &[]).is_ok());
+
+    let t = parse_template(r#"{{ a | pad() }}"#, &Delimiters::default(), &NO_PARTIALS)?;
+    assert!(validate_filters(&t, &[]).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn run_filter_arguments() -> Result<()> {
+    let t = parse_template(
+        r#"{{ a | replace("_", "'$x") }}|{{ a | prefix("pre ") | suffix(" suf") }}|{{ a | truncate(3) }}|{{ a | pad(8, ".") }}|{{ b | default("none") }}|{{ a | replace("", "x") }}"#,
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    let script = generate_bash_script("test", t, &[]);
+
+    assert!(script.contains(r#"a_prefix_suffix=$(suffix "$(prefix "$a" 'pre ')" ' suf')"#));
+    assert!(script.contains(r#"a_replace=$(replace "$a" '_' ''\''$x')"#));
+
+    // `b` is only used with `default`, so it's optional
+    assert_eq!(
+        run_script(&script, &["--a", "a_b"])?,
+        "a'$xb|pre a_b suf|a_b|a_b.....|none|a_b"
+    );
+    assert_eq!(
+        run_script(&script, &["--a", "long_name", "--b", "given"])?,
+        "long'$xname|pre long_name suf|lon|long_name|given|long_name"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn parse_error_locations() {
+    let location = |template: &str| {
+        let err = parse_template(template, &Delimiters::default(), &NO_PARTIALS).unwrap_err();
+        let err = err.downcast_ref::<ParseError>().unwrap();
+        (err.line, err.column, err.snippet.clone())
+    };
+
+    assert_eq!(
+        location("first line\nhello {{ a | uper( }} there"),
+        (
+            2,
+            7,
+            "  |\n2 | hello {{ a | uper( }} there\n  |       ^".to_string()
+        )
+    );
+
+    // unfinished variables point at the opening braces
+    assert_eq!(location("a\nb\n  c {{ unfinished").0, 3);
+    assert_eq!(location("a\nb\n  c {{ unfinished").1, 5);
+
+    // nested variables point at the inner braces
+    assert_eq!(location("{{ a {{ b }} }}").1, 6);
+
+    // missing ends point at the block that was opened
+    assert_eq!(location("{{ if a }}\n{{ for b in c }}\n{{ end }}").0, 1);
+    assert_eq!(location("{{ if a }}\n{{ end }}\n{{ end }}").0, 3);
+
+    let err = parse_template("x\n{{ a b }}", &Delimiters::default(), &NO_PARTIALS).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "identifier a b contains invalid characters (line 2, column 1)"
+    );
+}
+
+#[test]
+fn escaped_braces() -> Result<()> {
+    let template = r#"<p>{{ "{{" }} message }}</p> {{ name }} }}
+{{ raw }}{{ if x }}{{ "}}" }}{{ end }}{{raw}} {{ endraw }}
+<div v-if="{{"{{ a }}"}}">{{ value | upper }}{{ "\"\\" }}</div>"#;
+
+    let t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+    // nothing inside of the escapes is a variable
+    assert_eq!(
+        t.variables.iter().map(|v| v.variable).collect::<Vec<_>>(),
+        vec!["name", "value"]
+    );
+
+    let script = generate_bash_script("test", t, &[]);
+
+    assert_eq!(
+        run_script(&script, &["--name", "moho", "--value", "v"])?,
+        r#"<p>{{ message }}</p> moho }}
+{{ if x }}{{ "}}" }}{{ end }}{{raw}} 
+<div v-if="{{ a }}">V"\</div>"#
+    );
+
+    // the original template, escapes included, is kept for editing
+    assert_eq!(
+        run_script(&script, &["get-template"])?,
+        format!("{template}\n")
+    );
+
+    let script = generate_bash_script(
+        "test",
+        parse_template(r#"{{ "}}" }}"#, &Delimiters::default(), &NO_PARTIALS)?,
+        &[],
+    );
+    assert_eq!(run_script(&script, &[])?, "}}");
+
+    assert!(parse_template(
+        "{{ raw }} never closed {{ end }}",
+        &Delimiters::default(),
+        &NO_PARTIALS
+    )
+    .is_err());
+    assert!(parse_template(r#"{{ "unfinished }}"#, &Delimiters::default(), &NO_PARTIALS).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn backslashes_are_kept() -> Result<()> {
+    let template = r"namespace App\Http\Controllers\{{ name }};\n";
+    let script = generate_bash_script(
+        "test",
+        parse_template(template, &Delimiters::default(), &NO_PARTIALS)?,
+        &[],
+    );
+
+    assert_eq!(
+        run_script(&script, &["--name", "nova"])?,
+        "namespace App\\Http\\Controllers\\nova;\\n"
+    );
+    assert_eq!(
+        run_script(&script, &["get-template"])?,
+        format!("{template}\n")
+    );
+
+    Ok(())
+}
+
+#[test]
+fn custom_delimiters() -> Result<()> {
+    let delimiters: Delimiters = "<% %>".parse().map_err(|e: String| eyre!(e))?;
+
+    let template = r#"<div>{{ message }}</div> <% a | upper %><% if b %>!<% end %> <% "<%" %>"#;
+    let t = parse_template(template, &delimiters, &NO_PARTIALS)?;
+
+    assert_eq!(
+        t.variables.iter().map(|v| v.variable).collect::<Vec<_>>(),
+        vec!["a", "b", "name"]
+    );
+
+    let script = generate_bash_script("test", t, &[]);
+    assert_eq!(
+        run_script(&script, &["--a", "x", "--b", "y"])?,
+        "<div>{{ message }}</div> X! <%"
+    );
+
+    // the delimiters are saved, so `moho edit` can parse the template again
+    assert!(script.contains("\ndelimiters=[\"<%\", \"%>\"]\n"));
+    let vars: toml::Value = toml::from_str(&run_script(&script, &["get-vars"])?)?;
+    assert_eq!(vars["delimiters"], toml::Value::from(vec!["<%", "%>"]));
+
+    let err = parse_template("<% if a %>", &delimiters, &NO_PARTIALS).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "`<% if %>` block is missing an `<% end %>` (line 1, column 1)"
+    );
+
+    assert!("<%".parse::<Delimiters>().is_err());
+    assert!("a b c".parse::<Delimiters>().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn comments() -> Result<()> {
+    let template = "a{{# keep this in sync with {{ other }} #}}b {{ c }}{{#\nmultiline\n#}}\n";
+    let t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+
+    assert_eq!(
+        t.nodes,
+        vec![
+            Node::Text("a"),
+            Node::Text("b "),
+            Node::Variable("c"),
+            Node::Text("\n"),
+        ]
+    );
+    assert_eq!(t.original, template);
+
+    let script = generate_bash_script("test", t, &[]);
+    assert_eq!(run_script(&script, &["--c", "x"])?, "ab x\n");
+    assert_eq!(
+        run_script(&script, &["get-template"])?,
+        format!("{template}\n")
+    );
+
+    let delimiters = Delimiters::new("<%", "%>")?;
+    let t = parse_template("<%# comment #%>a", &delimiters, &NO_PARTIALS)?;
+    assert_eq!(t.nodes, vec![Node::Text("a")]);
+
+    assert!(parse_template("{{# unfinished }}", &Delimiters::default(), &NO_PARTIALS).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn whitespace_control() -> Result<()> {
+    let template = r#"<?php
+
+use App\Models;
+{{- if model }}
+use App\Models\{{ model }};
+{{- end }}
+
+class {{ name | pascal }}
+{
+    {{- for field in fields }}
+    public ${{ field }};
+    {{- end }}
+}
+"#;
+    let t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+    let script = generate_bash_script("test", t, &[]);
+
+    assert_eq!(
+        run_script(&script, &["--name", "user", "--fields", "id,email"])?,
+        r#"<?php
+
+use App\Models;
+
+class User
+{
+    public $id;
+    public $email;
+}
+"#
+    );
+    assert_eq!(
+        run_script(
+            &script,
+            &["--name", "user", "--fields", "id", "--model", "User"]
+        )?,
+        r#"<?php
+
+use App\Models;
+use App\Models\User;
+
+class User
+{
+    public $id;
+}
+"#
+    );
+
+    // trailing markers remove the whitespace after the tag
+    let t = parse_template(
+        "a   {{- b -}}   c {{ raw -}}\n x {{- endraw }}",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    assert_eq!(
+        t.nodes,
+        vec![
+            Node::Text("a"),
+            Node::Variable("b"),
+            Node::Text("c "),
+            Node::Text("x"),
+        ]
+    );
+
+    // comments take them too
+    let t = parse_template(
+        "a\n  {{-# note -#}}\n  b {{# note #-}} c {{-# note #}} d",
+        &Delimiters::default(),
+        &NO_PARTIALS,
+    )?;
+    assert_eq!(
+        t.nodes,
+        vec![
+            Node::Text("a"),
+            Node::Text("b "),
+            Node::Text("c"),
+            Node::Text(" d")
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn includes() -> Result<()> {
+    let files = std::collections::HashMap::from([
+        ("license", "// (c) {{ year }} {{ company }}\n"),
+        (
+            "header",
+            "{{ include \"license\" }}namespace {{ namespace }};\n",
+        ),
+        ("field", "public ${{ field | camel }};"),
+    ]);
+    let read = |name: &str| {
+        files
+            .get(name)
+            .map(|s| s.to_string())
+            .ok_or_else(|| eyre!("no partial {name}"))
+    };
+
+    let template = r#"<?php
+{{ include "header" }}
+class {{ name }} {
+{{- for field in fields }}
+    {{ include "field" }}
+{{- end }}
+}"#;
+    let delimiters = Delimiters::default();
+    let partials = Partials::load(template, &delimiters, read)?;
+    let t = parse_template(template, &delimiters, &partials)?;
+
+    // the partials' variables become the template's variables
+    assert_eq!(
+        t.variables.iter().map(|v| v.variable).collect::<Vec<_>>(),
+        vec!["year", "company", "namespace", "name", "fields"]
+    );
+    assert_eq!(t.original, template);
+
+    let script = generate_bash_script("test", t, &[]);
+    assert_eq!(
+        run_script(
+            &script,
+            &[
+                "--year",
+                "2022",
+                "--company",
+                "moho",
+                "--namespace",
+                "App",
+                "--name",
+                "User",
+                "--fields",
+                "first_name,email"
+            ]
+        )?,
+        r#"<?php
+// (c) 2022 moho
+namespace App;
+
+class User {
+    public $firstName;
+    public $email;
+}"#
+    );
+
+    Ok(())
+}
+
+#[test]
+fn invalid_includes() -> Result<()> {
+    let files = std::collections::HashMap::from([
+        ("a", "a {{ include \"b\" }}"),
+        ("b", "b {{ include \"c\" }}"),
+        ("c", "c {{ include \"a\" }}"),
+        ("broken", "\n{{ if x }}"),
+        ("unquoted", "x\n  {{ include header }}"),
+    ]);
+    let read = |name: &str| {
+        files
+            .get(name)
+            .map(|s| s.to_string())
+            .ok_or_else(|| eyre!("no partial {name}"))
+    };
+    let delimiters = Delimiters::default();
+
+    let template = r#"{{ include "a" }}"#;
+    let partials = Partials::load(template, &delimiters, read)?;
+    assert_eq!(
+        parse_template(template, &delimiters, &partials)
+            .unwrap_err()
+            .to_string(),
+        "partials include each other: a -> b -> c -> a in partial `c` in partial `b` in partial `a` (line 1, column 3)"
+    );
+
+    let template = r#"{{ include "broken" }}"#;
+    let partials = Partials::load(template, &delimiters, read)?;
+    let err = parse_template(template, &delimiters, &partials).unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!(err.line, 2);
+    assert_eq!(
+        err.message,
+        "`{{ if }}` block is missing an `{{ end }}` in partial `broken`"
+    );
+
+    // names that aren't strings point to the tag, in the template or in a partial
+    let err = Partials::load("a {{ include header }}", &delimiters, read).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "partial names should be strings (line 1, column 3)"
+    );
+    let err = Partials::load(r#"{{ include "unquoted" }}"#, &delimiters, read).unwrap_err();
+    let err = err.downcast_ref::<ParseError>().unwrap();
+    assert_eq!((err.line, err.column), (2, 3));
+    assert_eq!(
+        err.message,
+        "partial names should be strings in partial `unquoted`"
+    );
+
+    assert!(Partials::load(r#"{{ include "missing" }}"#, &delimiters, read).is_err());
+    assert!(parse_template(r#"{{ include "a" }}"#, &delimiters, &NO_PARTIALS).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn variable_types() -> Result<()> {
+    assert_eq!("bool".parse(), Ok(VariableType::Bool));
+    assert_eq!("integer".parse(), Ok(VariableType::Integer));
+    assert_eq!("".parse(), Ok(VariableType::String));
+    assert_eq!(
+        "model | controller".parse(),
+        Ok(VariableType::Enum(vec![
+            "model".to_string(),
+            "controller".to_string()
+        ]))
+    );
+    assert!("model||controller".parse::<VariableType>().is_err());
+    assert!("float".parse::<VariableType>().is_err());
+
+    assert!(VariableType::Integer.check("-12").is_ok());
+    assert!(VariableType::Integer.check("1.5").is_err());
+    assert!(VariableType::Bool.check("yes").is_err());
+    assert_eq!(
+        VariableType::Enum(vec!["model".to_string(), "controller".to_string()])
+            .check("view")
+            .unwrap_err()
+            .to_string(),
+        "expected one of model, controller, got `view`"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn run_typed_variables() -> Result<()> {
+    let template =
+        "{{ kind }} {{ count }}{{ if ctor }} with constructor{{ end }}{{ if soft }} soft{{ end }}";
+    let mut t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+    for v in &mut t.variables {
+        match v.variable {
+            "kind" => v.kind = "model|controller".parse().unwrap(),
+            "count" => v.kind = VariableType::Integer,
+            "ctor" => v.kind = VariableType::Bool,
+            "soft" => {
+                v.kind = VariableType::Bool;
+                v.default = Some("true".into());
+            }
+            _ => {}
+        }
+    }
+    let script = generate_bash_script("test", t, &[]);
+
+    let run = |args: &[&str]| {
+        let mut all = vec!["--name", "test", "--kind", "model", "--count", "3"];
+        all.extend(args);
+        run_script(&script, &all)
+    };
+
+    assert_eq!(run(&[])?, "model 3 soft");
+    assert_eq!(run(&["--ctor", "--no-soft"])?, "model 3 with constructor");
+    assert_eq!(run(&["--count", "-10"])?, "model -10 soft");
+
+    assert!(run(&["--count", "3x"]).is_err());
+    assert!(run(&["--count", "1-2"]).is_err());
+    assert!(run(&["--kind", "view"]).is_err());
+
+    let help = run(&["--help"])?;
+    assert!(help.contains("--kind model|controller"));
+    assert!(help.contains("--count INTEGER"));
+    assert!(help.contains("--[no-]ctor"));
+
+    Ok(())
+}
+
+#[test]
+fn inline_defaults_and_descriptions() -> Result<()> {
+    let template = r#"namespace {{ namespace = "App\\Http" # controller namespace }};
+class {{ model | pascal = "User" }} {{ model # the "model" # to use }}
+{{ price = "$5 `each`" }}"#;
+    let t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+
+    let namespace = &t.variables[0];
+    assert_eq!(namespace.variable, "namespace");
+    assert_eq!(namespace.default.as_deref(), Some(r"App\Http"));
+    assert_eq!(
+        namespace.description.as_deref(),
+        Some("controller namespace")
+    );
+    let model = &t.variables[1];
+    assert_eq!(model.default.as_deref(), Some("User"));
+    assert_eq!(
+        model.description.as_deref(),
+        Some(r#"the "model" # to use"#)
+    );
+
+    let script = generate_bash_script("test", t, &[]);
+    assert_eq!(
+        run_script(&script, &["--name", "a"])?,
+        "namespace App\\Http;\nclass User User\n$5 `each`"
+    );
+    assert!(run_script(&script, &["-h"])?.contains(r#"the "model" # to use"#));
+
+    // the metadata can be read back
+    let vars: toml::Value = toml::from_str(&run_script(&script, &["get-vars"])?)?;
+    assert_eq!(vars["defaults"]["namespace"].as_str(), Some(r"App\Http"));
+    assert_eq!(vars["defaults"]["price"].as_str(), Some("$5 `each`"));
+    assert_eq!(
+        vars["descriptions"]["model"].as_str(),
+        Some(r#"the "model" # to use"#)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn invalid_inline_defaults() {
+    let error = |template| {
+        parse_template(template, &Delimiters::default(), &NO_PARTIALS)
+            .unwrap_err()
+            .to_string()
+    };
+
+    assert_eq!(
+        error(r#"{{ a = "x" }} {{ a = "y" }}"#),
+        "variable a has two different defaults, `x` and `y` (line 1, column 15)"
+    );
+    assert_eq!(
+        error(r#"{{ a = x }}"#),
+        "expected a double quoted string, found `x` (line 1, column 1)"
+    );
+    assert_eq!(
+        error(r#"{{ for a in list }}{{ a = "x" }}{{ end }}"#),
+        "loop variable a can't have a default or a description (line 1, column 20)"
+    );
+}
+
+#[test]
+fn front_matter() -> Result<()> {
+    let template = r#"+++
+path = "app/Http/Controllers/name.php"
+description = "a controller for {{ model }}"
+messages = ["add a route for it", "costs $0"]
+
+[variables.namespace]
+default = "App\\Http"
+description = "controller namespace"
+
+[variables.ctor]
+type = "bool"
+default = true
+
+[variables.count]
+type = "integer"
+default = 3
++++
+namespace {{ namespace }};
+class {{ name }}{{ if ctor }} ctor{{ end }} {{ count }}"#;
+    let t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+
+    let front_matter = t.front_matter.as_ref().unwrap();
+    assert_eq!(
+        front_matter.path,
+        Some(PathBuf::from("app/Http/Controllers/name.php"))
+    );
+    assert_eq!(
+        t.variables[0].description.as_deref(),
+        Some("controller namespace")
+    );
+    assert_eq!(t.variables[2].kind, VariableType::Bool);
+    assert_eq!(t.variables[3].default.as_deref(), Some("3"));
+    assert_eq!(t.original, template);
+
+    let script = generate_bash_script("test", t, &[]);
+    assert_eq!(
+        run_script(&script, &["--name", "Users"])?,
+        "namespace App\\Http;\nclass Users ctor 3"
+    );
+    assert!(run_script(&script, &["-h"])?.starts_with("test: a controller for {{ model }}\n"));
+
+    let output = std::process::Command::new("/bin/sh")
+        .args(["-c", &script, "test", "--name", "a"])
+        .output()?;
+    assert_eq!(
+        String::from_utf8(output.stderr)?,
+        "add a route for it\ncosts $0\n"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn invalid_front_matter() -> Result<()> {
+    let error = |template| {
+        parse_template(template, &Delimiters::default(), &NO_PARTIALS)
+            .unwrap_err()
+            .to_string()
+    };
+
+    assert_eq!(
+        error("+++\npath = \"a\"\n{{ a }}"),
+        "front matter is missing its closing `+++` (line 1, column 1)"
+    );
+    assert!(error("+++\npath = \"a\"\nmessages = [\n+++\n").contains("(line 4, column 1)"));
+    assert_eq!(
+        error("+++\n[variables.b]\ndescription = \"b\"\n+++\n{{ a }}"),
+        "variable b is in the front matter, but not in the template"
+    );
+    assert_eq!(
+        error("+++\n[variables.a]\ntype = \"integer\"\ndefault = \"x\"\n+++\n{{ a }}"),
+        "default for a doesn't fit its type"
+    );
+    assert_eq!(
+        error("+++\n[variables.a]\ndefault = \"x\"\n+++\n{{ a = \"y\" }}"),
+        "variable a has two different defaults, `y` and `x`"
+    );
+
+    // partials can have front matter, but only their body is included
+    let files = std::collections::HashMap::from([(
+        "header",
+        "+++\ndescription = \"a header\"\n+++\n// {{ company }}\n",
+    )]);
+    let read = |name: &str| Ok(files[name].to_string());
+    let template = "{{ include \"header\" }}body";
+    let partials = Partials::load(template, &Delimiters::default(), read)?;
+    let t = parse_template(template, &Delimiters::default(), &partials)?;
+    assert!(t.front_matter.is_none());
+    assert_eq!(
+        run_script(
+            &generate_bash_script("test", t, &[]),
+            &["--name", "a", "--company", "moho"]
+        )?,
+        "// moho\nbody"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn create_arguments() -> Result<()> {
+    use clap::Parser;
+
+    let args = crate::Args::try_parse_from([
+        "moho",
+        "create",
+        "controller",
+        "--from-stdin",
+        "--default",
+        "namespace=App\\Http",
+        "--description",
+        "model=the model = the thing",
+    ])?;
+    let crate::Action::Create(args) = args.action else {
+        panic!("expected create");
+    };
+    assert!(args.from_stdin);
+    assert_eq!(
+        args.defaults,
+        vec![("namespace".to_string(), "App\\Http".to_string())]
+    );
+    assert_eq!(
+        args.descriptions,
+        vec![("model".to_string(), "the model = the thing".to_string())]
+    );
+
+    assert!(crate::Args::try_parse_from(["moho", "create", "a", "--default", "a"]).is_err());
+    assert!(crate::Args::try_parse_from([
+        "moho",
+        "create",
+        "a",
+        "--from-stdin",
+        "--template-file",
+        "a"
+    ])
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn create_with_vars() -> Result<()> {
+    use crate::create_template::apply_vars;
+
+    let template = r#"{{ namespace = "App" }} {{ model # the model }} {{ count }}"#;
+    let mut t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+    let vars: Vars = toml::from_str(
+        r#"
+default_path = "app/name.php"
+
+[types]
+count = "integer"
+
+[defaults]
+count = "3"
+model = "User"
+
+[descriptions]
+count = "how many"
+"#,
+    )?;
+
+    apply_vars(
+        &mut t,
+        &vars,
+        &[("model".into(), "Post".into())],
+        &[("model".into(), "the model to use".into())],
+    )?;
+    assert_eq!(t.variables[0].default.as_deref(), Some("App"));
+    assert_eq!(t.variables[1].default.as_deref(), Some("Post"));
+    assert_eq!(
+        t.variables[1].description.as_deref(),
+        Some("the model to use")
+    );
+    assert_eq!(t.variables[2].kind, VariableType::Integer);
+    assert_eq!(t.variables[2].default.as_deref(), Some("3"));
+
+    assert_eq!(
+        apply_vars(&mut t, &vars, &[("count".into(), "x".into())], &[])
+            .unwrap_err()
+            .to_string(),
+        "default for count doesn't fit its type"
+    );
+    assert_eq!(
+        apply_vars(
+            &mut t,
+            &Vars::default(),
+            &[("modle".into(), "x".into())],
+            &[]
+        )
+        .unwrap_err()
+        .to_string(),
+        "variable modle isn't used in the template"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn read_scripts_without_running_them() -> Result<()> {
+    let template = r#"+++
+[variables.model]
+default = "a \"quoted\" \\ $value"
++++
+MOHO_TEMPLATE
+if [ "$1" = "get-vars" ]; then
+cat <<'MOHO_VARS'
+{{ model }} `ls` $(ls)
+"#;
+    let mut t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+    parse_path(&mut t, Some("src/name.rs"))?;
+    let script = generate_bash_script("test", t, &[]);
+
+    let read = Script::parse(&script)?;
+    assert_eq!(read.template, template);
+    assert_eq!(read.vars.default_path, Some("src/name.rs".into()));
+    assert_eq!(read.vars.defaults["model"], r#"a "quoted" \ $value"#);
+
+    // the shell prints the same thing
+    assert_eq!(
+        run_script(&script, &["get-template"])?,
+        format!("{template}\n")
+    );
+
+    // scripts from older versions print double quoted strings
+    let old = r#"#!/bin/sh
+if [ "$1" = "get-template" ]; then
+printf '%s\n' "a \"b\" \\c \$d \`e\` {{ f }}"
+fi
+
+if [ "$1" = "get-vars" ]; then
+echo "
+default_path=\"./name.rs\"
+
+[defaults]
+f=\"g\"
+
+[descriptions]
+
+"
+fi
+"#;
+    let read = Script::parse(old)?;
+    assert_eq!(read.template, r#"a "b" \c $d `e` {{ f }}"#);
+    assert_eq!(read.vars.default_path, Some("./name.rs".into()));
+    assert_eq!(read.vars.defaults["f"], "g");
+
+    assert!(Script::parse("#!/bin/sh\necho hi").is_err());
+
+    Ok(())
+}
+
+#[test]
+fn script_headers() -> Result<()> {
+    let t = parse_template("hi {{ model }}", &Delimiters::default(), &NO_PARTIALS)?;
+    let script = generate_bash_script("test", t, &[]);
+
+    assert!(script.starts_with(&format!(
+        "#!/bin/sh\n# generated by moho, use `moho edit` instead of changing it by hand\n\
+         # moho-version: {}\n# moho-format: {SCRIPT_FORMAT}\n# moho-hash: ",
+        env!("CARGO_PKG_VERSION")
+    )));
+    assert_eq!(Status::of(&script), Status::Current);
+    assert_eq!(Script::parse(&script)?.status, Status::Current);
+    assert_eq!(
+        run_script(&script, &["--model", "a", "--name", "b"])?,
+        "hi a"
+    );
+
+    // any change after the header is noticed
+    let modified = script.replace("hi ", "hello ");
+    assert_eq!(Status::of(&modified), Status::Modified);
+    assert!(Status::Modified.warning("test").is_some());
+
+    let newer = script.replace(
+        &format!("# moho-format: {SCRIPT_FORMAT}"),
+        &format!("# moho-format: {}", SCRIPT_FORMAT + 1),
+    );
+    assert_eq!(
+        Status::of(&newer),
+        Status::Newer {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            format: SCRIPT_FORMAT + 1
+        }
+    );
+
+    assert_eq!(Status::of("#!/bin/sh\nset -e\n"), Status::Outdated);
+
+    Ok(())
+}
+
+#[test]
+fn upgrade_scripts() -> Result<()> {
+    use crate::upgrade_templates::regenerate;
+
+    let template = r#"{{ namespace = "App" }} {{ model | upper }}{{ if ctor }} ctor{{ end }}"#;
+    let mut t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+    t.variables[1].description = Some("the model".to_string());
+    t.variables[2].kind = VariableType::Bool;
+    parse_path(&mut t, Some("src/name.php"))?;
+    let script = generate_bash_script("test", t, &[]);
+
+    // current scripts stay the same
+    assert_eq!(regenerate("test", &script, &[])?, script);
+
+    // old scripts are generated with everything they had
+    let old = r#"#!/bin/sh
+set -e
+echo "old code"
+
+if [ "$1" = "get-template" ]; then
+printf '%s\n' "{{ namespace = \"App\" }} {{ model | upper }}{{ if ctor }} ctor{{ end }}"
+fi
+
+if [ "$1" = "get-vars" ]; then
+printf '%s\n' "
+default_path=\"src/name.php\"
+
+[defaults]
+namespace=\"App\"
+
+[types]
+ctor=\"bool\"
+
+[descriptions]
+model=\"the model\"
+
+"
+fi
+"#;
+    assert_eq!(regenerate("test", old, &[])?, script);
+
+    Ok(())
+}
+
+#[test]
+fn generators() -> Result<()> {
+    use crate::run_template::*;
+
+    let template = r#"+++
+variables.admin.type = "bool"
++++
+{{ file "app/Http/Controllers/name.php" -}}
+class {{ name }}Controller uses {{ model | pascal }}
+{{ end }}
+{{ file "app/Policies/name.php" -}}
+{{ if admin }}admin {{ end }}policy for {{ model }}
+{{ end }}
+"#;
+    let t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+    assert!(t.is_generator());
+    assert_eq!(
+        t.files.iter().map(|f| f.path.source).collect::<Vec<_>>(),
+        [
+            Some("app/Http/Controllers/name.php"),
+            Some("app/Policies/name.php")
+        ]
+    );
+    // the variables of every file are shared
+    assert_eq!(
+        t.variables.iter().map(|v| v.variable).collect::<Vec<_>>(),
+        ["name", "model", "admin"]
+    );
+
+    let help = help("resource", &t);
+    assert_eq!(
+        help[..4],
+        [
+            "resource:",
+            "generates files at:",
+            "  app/Http/Controllers/NAME.php",
+            "  app/Policies/NAME.php"
+        ]
+    );
+
+    // the script writes every file, even when its output is piped
+    let dir = std::env::temp_dir().join(format!("moho-generators-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let script = generate_bash_script("resource", t, &[]);
+    let output = std::process::Command::new("/bin/sh")
+        .current_dir(&dir)
+        .arg("-c")
+        .arg(&script)
+        .args(["test", "--model", "blog_post", "--admin", "--name", "Post"])
+        .output()?;
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout)?,
+        "created file at app/Http/Controllers/Post.php\ncreated file at app/Policies/Post.php\n"
+    );
+
+    let t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+    let args = ["--model", "blog_post", "--admin", "--name", "Post"].map(String::from);
+    let (values, _) = read_arguments(&t, &args)?.expect("the arguments are valid");
+    let (Output::Files(files), _) = render(&t, values, &[], false)? else {
+        panic!("generators render files");
+    };
+    assert_eq!(files.len(), 2);
+    for (path, content) in files {
+        assert_eq!(
+            std::fs::read_to_string(dir.join(&path))?,
+            content,
+            "{path:?}"
+        );
+    }
+    assert_eq!(
+        std::fs::read_to_string(dir.join("app/Policies/Post.php"))?,
+        "admin policy for blog_post\n"
+    );
+
+    // the name is needed for the paths
+    let output = std::process::Command::new("/bin/sh")
+        .current_dir(&dir)
+        .arg("-c")
+        .arg(&script)
+        .args(["test", "--model", "Post"])
+        .output()?;
+    assert!(!output.status.success());
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn invalid_generators() {
+    let errors = [
+        (
+            "hi {{ file \"a\" }}{{ end }}",
+            "templates with `{{ file }}` blocks can't have anything outside of them",
+        ),
+        (
+            "{{ if a }}{{ file \"a\" }}{{ end }}{{ end }}",
+            "`{{ file }}` blocks can't be inside of other blocks",
+        ),
+        (
+            "{{ file \"a\" }}{{ end }}{{ file \"a\" }}{{ end }}",
+            "there's already a file block for `a`",
+        ),
+        (
+            "{{ file \"a.txt\" }}{{ end }}{{ file \"b.txt\" }}{{ end }}",
+            "`b.txt` is written to the same file as `a.txt`",
+        ),
+        (
+            "{{ file \"src/name.rs\" }}{{ end }}{{ file \"src/name_test.rs\" }}{{ end }}",
+            "`src/name_test.rs` is written to the same file as `src/name.rs`",
+        ),
+        (
+            "{{ file \"src/name.rs\" }}{{ end }}{{ file \"src/{{ name }}.rs\" }}{{ end }}",
+            "`src/{{ name }}.rs` is written to the same file as `src/name.rs`",
+        ),
+        (
+            "{{ file \"a\" }}",
+            "`{{ file }}` block is missing an `{{ end }}`",
+        ),
+        (
+            "+++\npath = \"b\"\n+++\n{{ file \"a\" }}{{ end }}",
+            "templates with `{{ file }}` blocks can't have a path in their front matter",
+        ),
+    ];
+
+    for (template, error) in errors {
+        let e = parse_template(template, &Delimiters::default(), &NO_PARTIALS)
+            .expect_err(template)
+            .to_string();
+        assert!(e.starts_with(error), "{template:?} gave {e:?}");
+    }
+
+    // tagless paths in different folders or with different extensions are different files
+    let template = "{{ file \"a.txt\" }}{{ end }}{{ file \"a.md\" }}{{ end }}\
+                    {{ file \"b/a.txt\" }}{{ end }}";
+    let t = parse_template(template, &Delimiters::default(), &NO_PARTIALS).unwrap();
+    assert_eq!(t.files.len(), 3);
+
+    // a variable called `file` still works
+    let t = parse_template("{{ file }}", &Delimiters::default(), &NO_PARTIALS).unwrap();
+    assert!(!t.is_generator());
+}
+
+#[test]
+fn template_paths() -> Result<()> {
+    use crate::run_template::*;
+
+    // paths without tags replace their file name
+    let mut t = parse_template("hi", &Delimiters::default(), &NO_PARTIALS)?;
+    parse_path(&mut t, Some("src/models/name.rs"))?;
+    assert_eq!(
+        t.path.nodes,
+        [
+            Node::Text("src/models/"),
+            Node::Variable("name"),
+            Node::Text(".rs")
+        ]
+    );
+    assert!(t.path_uses_name());
+
+    let template = r#"+++
+path = "app/Models/{{ module }}/{{ name | pascal }}.php"
+
+[variables.module]
+default = "Core"
+description = "the module"
++++
+class {{ name | pascal }}"#;
+    let mut t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+    let path = t.front_matter.as_ref().and_then(|f| f.path.clone());
+    let path = path.as_ref().map(|p| p.to_string_lossy());
+    parse_path(&mut t, path.as_deref())?;
+
+    // the variables of the path become options, with the front matter applied to them
+    assert_eq!(
+        t.variables.iter().map(|v| v.variable).collect::<Vec<_>>(),
+        ["name", "module"]
+    );
+    assert_eq!(t.variables[1].default.as_deref(), Some("Core"));
+    assert_eq!(
+        help("model", &t)[1],
+        "generates file at app/Models/MODULE/NAME.php"
+    );
+
+    let (values, _) = read_arguments(&t, &["--name".to_string(), "blog_post".to_string()])?
+        .expect("the arguments are valid");
+    let (output, _) = render(&t, values, &[], true)?;
+    assert_eq!(
+        output,
+        Output::Single(
+            "class BlogPost".to_string(),
+            "app/Models/Core/BlogPost.php".into()
+        )
+    );
+
+    let script = generate_bash_script("model", t, &[]);
+    assert!(script.contains("\n_moho_path=\"app/Models/${module}/${name_pascal}.php\"\n"));
+    assert_eq!(run_script(&script, &["--name", "post"])?, "class Post");
+
+    // paths that don't use the name don't need it
+    let mut t = parse_template("hi", &Delimiters::default(), &NO_PARTIALS)?;
+    parse_path(&mut t, Some("config/{{ file = \"app\" }}.php"))?;
+    assert!(!t.path_uses_name());
+    let (values, _) = read_arguments(&t, &[])?.unwrap();
+    let (output, _) = render(&t, values, &[], true)?;
+    assert_eq!(
+        output,
+        Output::Single("hi".to_string(), "config/app.php".into())
+    );
+
+    // the text of the path is escaped
+    let mut t = parse_template("hi", &Delimiters::default(), &NO_PARTIALS)?;
+    parse_path(&mut t, Some("$HOME/\"{{ name }}\".txt"))?;
+    let script = generate_bash_script("test", t, &[]);
+    assert!(script.contains("\n_moho_path=\"\\$HOME/\\\"${name}\\\".txt\"\n"));
+
+    Ok(())
+}
+
+#[test]
+fn invalid_template_paths() -> Result<()> {
+    let errors = [
+        (
+            "src/{{ if a }}a{{ end }}.rs",
+            "paths can only have variables and filters",
+        ),
+        (
+            "src/{{ end }}.rs",
+            "found `{{ end }}` without a matching block",
+        ),
+        (
+            "src/{{ a b }}.rs",
+            "identifier a b contains invalid characters",
+        ),
+    ];
+    for (path, error) in errors {
+        let mut t = parse_template("hi", &Delimiters::default(), &NO_PARTIALS)?;
+        let e = parse_path(&mut t, Some(path)).expect_err(path).to_string();
+        assert!(e.starts_with(error), "{path:?} gave {e:?}");
+    }
+
+    // the front matter can't describe variables that aren't anywhere
+    let template = "+++\npath = \"src/{{ module }}/name.rs\"\n[variables.other]\n+++\nhi";
+    assert!(parse_template(template, &Delimiters::default(), &NO_PARTIALS).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn inject() -> Result<()> {
+    use crate::run_template::*;
+
+    let dir = std::env::temp_dir().join(format!("moho-inject-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let file = dir.join("web.php");
+
+    let cases = [
+        (
+            "after = \"^use \"",
+            "<?php\nuse Foo;\n\nroutes\n",
+            "<?php\nuse Foo;\nuse $App\\User;\n\nroutes\n",
+        ),
+        (
+            "before = \"^routes$\"",
+            "<?php\nroutes",
+            "<?php\nuse $App\\User;\nroutes\n",
+        ),
+        ("", "<?php\nroutes", "<?php\nroutes\nuse $App\\User;\n"),
+        ("", "<?php\n", "<?php\nuse $App\\User;\n"),
+    ];
+
+    for (settings, original, expected) in cases {
+        let template = format!(
+            "+++\npath = \"{}\"\n[inject]\n{settings}\n+++\nuse {{{{ model }}}}\\User;\n",
+            file.display()
+        );
+        let mut t = parse_template(&template, &Delimiters::default(), &NO_PARTIALS)?;
+        let path = file.to_string_lossy();
+        parse_path(&mut t, Some(&path))?;
+        let (values, _) =
+            read_arguments(&t, &["--model".to_string(), "$App".to_string()])?.unwrap();
+        let (Output::Single(out, path), _) = render(&t, values, &[], true)? else {
+            panic!("templates that inject have a single output");
+        };
+        assert_eq!(path, file);
+
+        let inject = t
+            .front_matter
+            .as_ref()
+            .and_then(|f| f.inject.as_ref())
+            .unwrap();
+        std::fs::write(&file, original)?;
+        inject_into(&file, &out, inject, false)?;
+        assert_eq!(std::fs::read_to_string(&file)?, expected, "{settings}");
+        // running it again doesn't change anything
+        inject_into(&file, &out, inject, false)?;
+        assert_eq!(std::fs::read_to_string(&file)?, expected, "{settings}");
+
+        // dry runs don't change the file
+        std::fs::write(&file, original)?;
+        inject_into(&file, &out, inject, true)?;
+        assert_eq!(std::fs::read_to_string(&file)?, original, "{settings}");
+
+        let script = generate_bash_script("test", t, &[]);
+        assert!(run_in(&dir, &script, &["--model", "$App", "--dry-run"])?.0);
+        assert_eq!(std::fs::read_to_string(&file)?, original, "{settings}");
+        // piped, it's only printed
+        assert!(run_in(&dir, &script, &["--model", "$App"])?.0);
+        assert_eq!(std::fs::read_to_string(&file)?, original, "{settings}");
+        for _ in 0..2 {
+            assert!(run_in(&dir, &script, &["--model", "$App", "--force"])?.0);
+            assert_eq!(std::fs::read_to_string(&file)?, expected, "{settings}");
+        }
+    }
+
+    // the pattern has to match some line
+    let template = format!(
+        "+++\npath = \"{}\"\n[inject]\nafter = \"^nope\"\n+++\nhi\n",
+        file.display()
+    );
+    let mut t = parse_template(&template, &Delimiters::default(), &NO_PARTIALS)?;
+    let path = file.to_string_lossy();
+    parse_path(&mut t, Some(&path))?;
+    std::fs::write(&file, "hello\n")?;
+    let inject = t
+        .front_matter
+        .as_ref()
+        .and_then(|f| f.inject.as_ref())
+        .unwrap();
+    assert!(inject_into(&file, "hi\n", inject, false).is_err());
+    let script = generate_bash_script("test", t, &[]);
+    assert!(!run_in(&dir, &script, &["--force"])?.0);
+    assert_eq!(std::fs::read_to_string(&file)?, "hello\n");
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn invalid_inject() {
+    let errors = [
+        "+++\n[inject]\nbefore = \"a\"\nafter = \"b\"\n+++\n",
+        "+++\n[inject]\nafter = \"(\"\n+++\n",
+        // only posix extended regexes, which is what awk matches
+        "+++\n[inject]\nafter = '^mod \\w+\\d;'\n+++\n",
+        "+++\n[inject]\nafter = 'a{2}'\n+++\n",
+        "+++\n[inject]\nafter = '[\\d]'\n+++\n",
+        "+++\n[inject]\n+++\n{{ file \"a\" }}{{ end }}",
+    ];
+    for template in errors {
+        assert!(
+            parse_template(template, &Delimiters::default(), &NO_PARTIALS).is_err(),
+            "{template:?}"
+        );
+    }
+
+    // the file to inject into is needed
+    let mut t =
+        parse_template("+++\n[inject]\n+++\n", &Delimiters::default(), &NO_PARTIALS).unwrap();
+    assert!(parse_path(&mut t, None).is_err());
+}
+
+#[test]
+fn standard_flags() -> Result<()> {
+    use crate::run_template::*;
+
+    let dir = std::env::temp_dir().join(format!("moho-flags-{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let run = |script: &str, args: &[&str]| run_in(&dir, script, args);
+
+    let mut t = parse_template("hello {{ name }}\n", &Delimiters::default(), &NO_PARTIALS)?;
+    parse_path(&mut t, Some("out/name.txt"))?;
+    assert_eq!(
+        read_arguments(&t, &["--dry-run".to_string(), "--force".to_string()])?,
+        Some((
+            std::collections::HashMap::from([("name".to_string(), String::new())]),
+            Options {
+                output: Some(OutputMode::DryRun),
+                overwrite: Overwrite::Yes,
+                interactive: false,
+            }
+        ))
+    );
+    let script = generate_bash_script("test", t, &[]);
+    let file = dir.join("out/x.txt");
+
+    assert_eq!(
+        run(&script, &["--name", "x", "--dry-run"])?,
+        (true, "would write to out/x.txt:\nhello x\n".to_string())
+    );
+    assert_eq!(
+        run(&script, &["--name", "x", "--stdout"])?,
+        (true, "hello x\n".to_string())
+    );
+    assert!(!file.exists());
+
+    // piped, the output is printed instead of written, unless saying what to do with the file
+    assert_eq!(
+        run(&script, &["--name", "x"])?,
+        (true, "hello x\n".to_string())
+    );
+    assert!(!file.exists());
+    assert!(run(&script, &["--name", "x", "--no-overwrite"])?.0);
+    assert_eq!(std::fs::read_to_string(&file)?, "hello x\n");
+    std::fs::write(&file, "changed")?;
+    assert!(!run(&script, &["--name", "x", "--no-overwrite"])?.0);
+    assert_eq!(std::fs::read_to_string(&file)?, "changed");
+    assert!(run(&script, &["--name", "x", "--force"])?.0);
+    assert_eq!(std::fs::read_to_string(&file)?, "hello x\n");
+    // the last one wins
+    assert!(!run(&script, &["--name", "x", "--force", "--no-overwrite"])?.0);
+
+    // generators print every file
+    let template = "{{ file \"a/{{ name }}.txt\" }}a {{ name }}\n{{ end }}\
+                    {{ file \"b/{{ name }}.txt\" }}b {{ name }}\n{{ end }}";
+    let t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+    let script = generate_bash_script("test", t, &[]);
+    assert_eq!(
+        run(&script, &["--name", "x", "--dry-run"])?,
+        (
+            true,
+            "would write to a/x.txt:\na x\n\nwould write to b/x.txt:\nb x\n".to_string()
+        )
+    );
+    assert!(!dir.join("a/x.txt").exists());
+    std::fs::create_dir_all(dir.join("b"))?;
+    std::fs::write(dir.join("b/x.txt"), "changed")?;
+    assert_eq!(run(&script, &["--name", "x", "--no-overwrite"])?.0, false);
+    assert!(!dir.join("a/x.txt").exists());
+    assert!(run(&script, &["--name", "x", "--force"])?.0);
+    assert_eq!(std::fs::read_to_string(dir.join("b/x.txt"))?, "b x\n");
+
+    // the template's variables come first
+    let t = parse_template("{{ force }}", &Delimiters::default(), &NO_PARTIALS)?;
+    let (values, options) = read_arguments(&t, &["--force".to_string(), "yes".to_string()])?
+        .expect("the arguments are valid");
+    assert_eq!(values["force"], "yes");
+    assert_eq!(options, Options::default());
+    let script = generate_bash_script("test", t, &[]);
+    assert_eq!(run_script(&script, &["--force", "yes"])?, "yes");
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn prompts() -> Result<()> {
+    use crate::run_template::*;
+    use std::io::Write;
+
+    let template = r#"+++
+[variables]
+model.description = "the model"
+admin.type = "bool"
+kind.type = "model|view"
+kind.default = "view"
++++
+{{ model }} {{ if admin }}admin {{ end }}{{ kind }}"#;
+    let t = parse_template(template, &Delimiters::default(), &NO_PARTIALS)?;
+    let prompts = t.variables.iter().map(prompt).collect::<Vec<_>>();
+    assert_eq!(
+        prompts,
+        [
+            "model (the model)",
+            "admin (y/n)",
+            "kind (one of model, view)",
+            "name (filename without extension)"
+        ]
+    );
+    let (_, options) = read_arguments(&t, &["--interactive".to_string()])?.unwrap();
+    assert!(options.interactive);
+
+    let mut t = t;
+    parse_path(&mut t, None)?;
+    let script = generate_bash_script("test", t, &[]);
+    let run = |args: &[&str], input: &str| {
+        let mut child = std::process::Command::new("/bin/sh")
+            .arg("-c")
+            .arg(&script)
+            .arg("test")
+            .args(args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()?;
+        // the script can exit before reading its input
+        match child.stdin.take().unwrap().write_all(input.as_bytes()) {
+            Err(e) if e.kind() != std::io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => {}
+        }
+        let output = child.wait_with_output()?;
+        Result::<_>::Ok((
+            String::from_utf8(output.stdout)?,
+            String::from_utf8(output.stderr)?,
+        ))
+    };
+
+    // every variable is asked for, empty answers keep the value
+    let (out, prompts) = run(&["--interactive", "--model", "Post"], "\ny\nmodel\n")?;
+    assert_eq!(out, "Post admin model");
+    assert_eq!(
+        prompts,
+        "model (the model) [Post]: admin (y/n) [n]: kind (one of model, view) [view]: \
+         name (filename without extension): "
+    );
+    let (out, _) = run(&["--interactive", "--admin"], "User\nno\n\n")?;
+    assert_eq!(out, "User view");
+
+    // without a terminal, missing values are still errors
+    let (out, prompts) = run(&[], "User\n")?;
+    assert_eq!(out, "Error: No value provided for model\n");
+    assert_eq!(prompts, "");
+
+    Ok(())
+}
+
+#[test]
+fn inject_patterns() -> Result<()> {
+    use crate::ere::Ere;
+
+    let lines = [
+        "",
+        "use App\\Models;",
+        "mod parse;",
+        "mod  tests; // x",
+        "pub mod a1;",
+        "Route::get('/', fn);",
+        "a]b-c",
+        "ab ab",
+        "tab\there",
+        "{}",
+        "x[&",
+        "aaaab",
+    ];
+    let patterns = [
+        "^use ",
+        "^mod [a-z_]+;$",
+        "mod",
+        "^(pub )?mod [[:alnum:]]+;",
+        "[[:space:]]//",
+        "^Route::(get|post)\\(",
+        "[]]b",
+        "[^a-z]",
+        "[a-]c",
+        "(ab ?)+$",
+        "^$",
+        "x*",
+        "^.?$",
+        "[[:blank:]]here",
+        "\\{\\}",
+        "a|tab",
+        "((a|b)c)?-",
+        "x[[&]",
+        "[a&&b]",
+        "(a|a)*b",
+        "(a*)*b",
+    ];
+
+    // `moho run` matches the same lines as the awk of the scripts
+    for pattern in patterns {
+        let ere = Ere::parse(pattern)?;
+        for line in lines {
+            let awk = std::process::Command::new("awk")
+                .env("_moho_pattern", pattern)
+                .arg("$0 ~ ENVIRON[\"_moho_pattern\"] { found = 1 } END { exit !found }")
+                .stdin(std::process::Stdio::piped())
+                .stdout(std::process::Stdio::null())
+                .spawn()
+                .and_then(|mut child| {
+                    use std::io::Write;
+                    let mut stdin = child.stdin.take().unwrap();
+                    writeln!(stdin, "{line}")?;
+                    drop(stdin);
+                    child.wait()
+                })?;
+            assert_eq!(ere.is_match(line), awk.success(), "{pattern:?} on {line:?}");
+        }
+    }
+
+    // these take exponential time for matchers that backtrack, like some awks
+    let line = "a".repeat(100);
+    assert!(!Ere::parse("(a|a)*b")?.is_match(&line));
+    assert!(!Ere::parse("(a*)*b")?.is_match(&line));
+
+    let invalid = [
+        "\\w",
+        "a{2}",
+        "}",
+        "[\\d]",
+        "(",
+        ")",
+        "a(|b)",
+        "()",
+        "*a",
+        "a**",
+        "[a",
+        "[[:word:]]",
+        "[[=a=]]",
+        "é",
+        "[z-a]",
+    ];
+    for pattern in invalid {
+        assert!(Ere::parse(pattern).is_err(), "{pattern:?}");
+    }
+
+    Ok(())
+}
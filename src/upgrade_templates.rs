@@ -0,0 +1,85 @@
+use crate::{create_template::apply_vars, filters::*, list_templates::template_names};
+
+use super::*;
+
+/// regenerates every template from its original source, with the current version of moho
+///
+/// with `check`, only reports the templates that would change, and fails if there are any
+pub fn upgrade_templates(check: bool) -> Result<()> {
+    let custom_filters = read_custom_filters()?;
+    let custom_filters = parse_custom_filters(&custom_filters);
+
+    // one broken template doesn't stop the others from being upgraded
+    let mut outdated = Vec::new();
+    let mut failed = 0;
+    for name in template_names()? {
+        match upgrade_template(&name, check, &custom_filters) {
+            Ok(true) => outdated.push(name),
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("error: couldn't upgrade template `{name}`: {e:#}");
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(eyre!("{failed} template(s) couldn't be upgraded"));
+    }
+    if check && !outdated.is_empty() {
+        return Err(eyre!(
+            "{} template(s) out of date, run `moho upgrade` to update them",
+            outdated.len()
+        ));
+    }
+
+    Ok(())
+}
+
+/// upgrades one template, returning whether it was out of date
+fn upgrade_template(name: &str, check: bool, custom_filters: &[CustomFilter]) -> Result<bool> {
+    let path = format!(".moho/{name}.mh");
+    let old = std::fs::read_to_string(&path)?;
+
+    // upgrading would turn it into an older format
+    let status = Status::of(&old);
+    if let Status::Newer { version, .. } = &status {
+        eprintln!(
+            "warning: skipped `{name}`, it was generated by moho {version}, \
+             which is newer than this one"
+        );
+        return Ok(false);
+    }
+
+    let new = regenerate(name, &old, custom_filters)?;
+    if old == new {
+        return Ok(false);
+    }
+
+    if check {
+        println!("{name} is out of date");
+    } else {
+        if status == Status::Modified {
+            eprintln!("warning: the changes made by hand to `{name}` were undone");
+        }
+        crate::helpers::write_template(name, &new)?;
+        println!("upgraded {name}");
+    }
+
+    Ok(true)
+}
+
+/// generates the script of a template again, from the source and metadata saved in it
+pub fn regenerate(name: &str, script: &str, custom_filters: &[CustomFilter]) -> Result<String> {
+    let Script { template, vars, .. } = Script::parse(script)?;
+    let delimiters = vars.delimiters()?;
+
+    let partials = Partials::load(&template, &delimiters, read_partial)?;
+    let mut parsed = parse_template(&template, &delimiters, &partials)?;
+    let default_path = vars.default_path.as_ref().map(|p| p.to_string_lossy());
+    parse_path(&mut parsed, default_path.as_deref())?;
+    validate_filters(&parsed, custom_filters)?;
+    apply_vars(&mut parsed, &vars, &[], &[])?;
+
+    Ok(generate_bash_script(name, parsed, custom_filters))
+}